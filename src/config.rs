@@ -1,54 +1,602 @@
-use serde::Deserialize;
+use regex::Regex;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
 use std::collections::HashMap;
 use std::fs;
 
-pub type Condition = HashMap<String, String>;
+#[derive(Debug, Clone)]
+pub enum Condition {
+    Equals {
+        variable: String,
+        value: String,
+        negate: bool,
+    },
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+    Not(Box<Condition>),
+    Matches {
+        variable: String,
+        regex: Regex,
+    },
+    LessThan {
+        variable: String,
+        value: f64,
+    },
+    GreaterThan {
+        variable: String,
+        value: f64,
+    },
+    IsSet(String),
+    IsEmpty(String),
+    // Path is a template, with variables substituted when the condition is checked
+    FileExists(String),
+}
+
+impl Condition {
+    // Variables the condition depends on, not counting the ones used in templates
+    pub fn variables(&self) -> Vec<&str> {
+        match self {
+            Condition::Equals { variable, .. }
+            | Condition::Matches { variable, .. }
+            | Condition::LessThan { variable, .. }
+            | Condition::GreaterThan { variable, .. }
+            | Condition::IsSet(variable)
+            | Condition::IsEmpty(variable) => vec![variable],
+            Condition::All(conditions) | Condition::Any(conditions) => conditions
+                .iter()
+                .flat_map(|condition| condition.variables())
+                .collect(),
+            Condition::Not(condition) => condition.variables(),
+            Condition::FileExists(_) => vec![],
+        }
+    }
+
+    // If the condition uses templates, it can change when any variable is set
+    pub fn uses_templates(&self) -> bool {
+        match self {
+            Condition::All(conditions) | Condition::Any(conditions) => conditions
+                .iter()
+                .any(|condition| condition.uses_templates()),
+            Condition::Not(condition) => condition.uses_templates(),
+            Condition::FileExists(_) => true,
+            _ => false,
+        }
+    }
+}
+
+fn condition_strings(value: serde_yaml::Value) -> Result<Vec<String>, String> {
+    match value {
+        serde_yaml::Value::Sequence(items) => items
+            .into_iter()
+            .map(|item| yaml_to_string(item).ok_or_else(|| "expected a string".to_string()))
+            .collect(),
+        value => Ok(vec![
+            yaml_to_string(value).ok_or_else(|| "expected a string".to_string())?
+        ]),
+    }
+}
+
+// Map of variable: value, used by matches, lt and gt
+fn condition_pairs(value: serde_yaml::Value) -> Result<Vec<(String, String)>, String> {
+    match value {
+        serde_yaml::Value::Mapping(map) => map
+            .into_iter()
+            .map(
+                |(key, value)| match (yaml_to_string(key), yaml_to_string(value)) {
+                    (Some(key), Some(value)) => Ok((key, value)),
+                    _ => Err("expected a map of variable: value".to_string()),
+                },
+            )
+            .collect(),
+        _ => Err("expected a map of variable: value".to_string()),
+    }
+}
+
+fn parse_number(value: &str) -> Result<f64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} is not a number", value))
+}
+
+// Every entry in a map is a condition, either a keyword or variable: value.
+// Keywords can't be used as variable names, a keyword given a plain value is reported
+// instead of being compared as a variable
+fn parse_condition_entries(map: serde_yaml::Mapping) -> Result<Vec<Condition>, String> {
+    let mut conditions = vec![];
+    for (key, value) in map.into_iter() {
+        let key =
+            yaml_to_string(key).ok_or_else(|| "condition keys must be strings".to_string())?;
+        match key.as_str() {
+            "all" | "any" | "not" | "matches" | "lt" | "gt"
+                if yaml_to_string(value.clone()).is_some() =>
+            {
+                return Err(format!(
+                    "{} is a keyword and expects a map or a list, not a value",
+                    key
+                ))
+            }
+            "all" => conditions.push(Condition::All(parse_condition_list(value)?)),
+            "any" => conditions.push(Condition::Any(parse_condition_list(value)?)),
+            "not" => conditions.push(Condition::Not(Box::new(parse_condition(value)?))),
+            "matches" => {
+                for (variable, regex) in condition_pairs(value)? {
+                    let regex = Regex::new(&regex).map_err(|e| e.to_string())?;
+                    conditions.push(Condition::Matches { variable, regex });
+                }
+            }
+            "lt" => {
+                for (variable, value) in condition_pairs(value)? {
+                    let value = parse_number(&value)?;
+                    conditions.push(Condition::LessThan { variable, value });
+                }
+            }
+            "gt" => {
+                for (variable, value) in condition_pairs(value)? {
+                    let value = parse_number(&value)?;
+                    conditions.push(Condition::GreaterThan { variable, value });
+                }
+            }
+            "is_set" => {
+                conditions.extend(condition_strings(value)?.into_iter().map(Condition::IsSet))
+            }
+            "is_empty" => conditions.extend(
+                condition_strings(value)?
+                    .into_iter()
+                    .map(Condition::IsEmpty),
+            ),
+            "file_exists" => conditions.extend(
+                condition_strings(value)?
+                    .into_iter()
+                    .map(Condition::FileExists),
+            ),
+            _ => {
+                let value = yaml_to_string(value)
+                    .ok_or_else(|| format!("value for {} must be a string", key))?;
+                let (variable, negate) = match key.strip_suffix('!') {
+                    Some(variable) => (variable.to_string(), true),
+                    None => (key, false),
+                };
+                conditions.push(Condition::Equals {
+                    variable,
+                    value,
+                    negate,
+                });
+            }
+        }
+    }
+    Ok(conditions)
+}
+
+// Conditions for any and all, either as a list or a map with one condition per entry
+fn parse_condition_list(value: serde_yaml::Value) -> Result<Vec<Condition>, String> {
+    match value {
+        serde_yaml::Value::Sequence(items) => items.into_iter().map(parse_condition).collect(),
+        serde_yaml::Value::Mapping(map) => parse_condition_entries(map),
+        _ => Err("expected a list of conditions".to_string()),
+    }
+}
+
+fn parse_condition(value: serde_yaml::Value) -> Result<Condition, String> {
+    let mut conditions = match value {
+        serde_yaml::Value::Mapping(map) => parse_condition_entries(map)?,
+        serde_yaml::Value::Sequence(items) => items
+            .into_iter()
+            .map(parse_condition)
+            .collect::<Result<_, _>>()?,
+        _ => return Err("a condition must be a map or a list".to_string()),
+    };
+    if conditions.len() == 1 {
+        Ok(conditions.remove(0))
+    } else {
+        Ok(Condition::All(conditions))
+    }
+}
+
+impl<'de> Deserialize<'de> for Condition {
+    fn deserialize<D>(deserializer: D) -> Result<Condition, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        parse_condition(serde_yaml::Value::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "type")]
 pub enum Action {
-    Run { command: Vec<String> },
-    Show { container: String },
-    Var { name: String, value: Option<String> },
-    Options { variable: String, container: String },
-    Image { variable: String, container: String },
+    Run {
+        command: Vec<String>,
+    },
+    Show {
+        container: String,
+        scroll: Option<Scroll>,
+        max_height: Option<i32>,
+    },
+    Var {
+        name: String,
+        value: Option<String>,
+    },
+    Options {
+        variable: String,
+        container: String,
+        delimiter: Option<String>,
+        #[serde(default, deserialize_with = "optional_regex")]
+        regex: Option<Regex>,
+        json: Option<OptionKeys>,
+        default: Option<String>,
+        none: Option<String>,
+    },
+    Image {
+        variable: String,
+        container: String,
+    },
+    MultiSelect {
+        variable: String,
+        container: String,
+        delimiter: Option<String>,
+        #[serde(default, deserialize_with = "optional_regex")]
+        regex: Option<Regex>,
+        json: Option<OptionKeys>,
+        #[serde(default)]
+        default: Vec<String>,
+        separator: Option<String>,
+    },
+    SetTab {
+        tabs: String,
+        page: String,
+    },
+    Table {
+        table: String,
+        format: Option<TableFormat>,
+        header: Option<bool>,
+    },
+    Message {
+        text: Option<String>,
+    },
+    Error {
+        text: Option<String>,
+    },
+    Confirm {
+        text: String,
+    },
+    Prompt {
+        text: String,
+        variable: String,
+        #[serde(default)]
+        password: bool,
+    },
+    Notify {
+        title: String,
+        body: Option<String>,
+        icon: Option<String>,
+        urgency: Option<Urgency>,
+    },
+    CopyToClipboard {
+        text: Option<String>,
+        image: Option<String>,
+    },
+    PasteFromClipboard {
+        variable: String,
+    },
+    Sleep {
+        ms: u32,
+    },
+    SetTitle {
+        title: String,
+    },
 }
 
 #[derive(Debug, Clone, Deserialize)]
+pub enum Urgency {
+    Low,
+    Normal,
+    High,
+    Urgent,
+}
+
+fn optional_regex<'de, D>(deserializer: D) -> Result<Option<Regex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Regex::new(&String::deserialize(deserializer)?)
+        .map(Some)
+        .map_err(D::Error::custom)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OptionKeys {
+    pub value: String,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum TableFormat {
+    Csv,
+    Tsv,
+    Whitespace,
+    Json,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum Align {
+    Fill,
+    Start,
+    End,
+    Center,
+    Baseline,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum Scroll {
+    Horizontal,
+    Vertical,
+    Both,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct Placement {
     pub spacing: Option<u32>,
     pub x: Option<i32>,
     pub y: Option<i32>,
     pub w: Option<i32>,
     pub h: Option<i32>,
+    pub expand: Option<bool>,
+    pub fill: Option<bool>,
+    pub halign: Option<Align>,
+    pub valign: Option<Align>,
+    pub margin: Option<i32>,
+    pub margin_top: Option<i32>,
+    pub margin_bottom: Option<i32>,
+    pub margin_start: Option<i32>,
+    pub margin_end: Option<i32>,
+    pub min_width: Option<i32>,
+    pub min_height: Option<i32>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ButtonNode {
     pub text: String,
+    // An underscore in the text marks the mnemonic
+    #[serde(default)]
+    pub mnemonic: bool,
     pub on_click: Vec<Action>,
+    pub on_error: Option<Vec<Action>>,
+    // Key combination that clicks the button, like <Ctrl>s
+    pub accelerator: Option<String>,
+    #[serde(default)]
     pub placement: Placement,
     pub active_when: Option<Condition>,
+    pub visible_when: Option<Condition>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct RadioButtonsNode {
     pub variable: String,
-    pub options: HashMap<String, String>,
+    #[serde(deserialize_with = "ordered_options")]
+    pub options: Vec<(String, String)>,
+    pub default: Option<String>,
+    pub on_change: Option<Vec<Action>>,
+    pub debounce: Option<u32>,
+    pub on_error: Option<Vec<Action>>,
+    #[serde(default)]
+    pub placement: Placement,
+    pub visible_when: Option<Condition>,
+}
+
+fn yaml_to_string(value: serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(string) => Some(string),
+        serde_yaml::Value::Number(number) => Some(number.to_string()),
+        serde_yaml::Value::Bool(boolean) => Some(boolean.to_string()),
+        _ => None,
+    }
+}
+
+// Options can be given as a map of value: label (kept in the order they are written),
+// or as a list of values or {value, label} pairs
+fn ordered_options<'de, D>(deserializer: D) -> Result<Vec<(String, String)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OptionItem {
+        Value(String),
+        Labeled {
+            value: String,
+            label: Option<String>,
+        },
+    }
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Options {
+        List(Vec<OptionItem>),
+        Map(serde_yaml::Mapping),
+    }
+    match Options::deserialize(deserializer)? {
+        Options::List(items) => Ok(items
+            .into_iter()
+            .map(|item| match item {
+                OptionItem::Value(value) => (value.clone(), value),
+                OptionItem::Labeled { value, label } => (value.clone(), label.unwrap_or(value)),
+            })
+            .collect()),
+        Options::Map(map) => map
+            .into_iter()
+            .map(
+                |(value, label)| match (yaml_to_string(value), yaml_to_string(label)) {
+                    (Some(value), Some(label)) => Ok((value, label)),
+                    _ => Err(D::Error::custom("options must be strings")),
+                },
+            )
+            .collect(),
+    }
+}
+
+// Map of strings, kept in the order they are written
+fn ordered_map<'de, D>(deserializer: D) -> Result<Vec<(String, String)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    serde_yaml::Mapping::deserialize(deserializer)?
+        .into_iter()
+        .map(
+            |(key, value)| match (yaml_to_string(key), yaml_to_string(value)) {
+                (Some(key), Some(value)) => Ok((key, value)),
+                _ => Err(D::Error::custom("expected a map of strings")),
+            },
+        )
+        .collect()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MultiSelectNode {
+    pub variable: String,
+    #[serde(deserialize_with = "ordered_options")]
+    pub options: Vec<(String, String)>,
+    #[serde(default)]
+    pub default: Vec<String>,
+    pub separator: Option<String>,
+    pub on_change: Option<Vec<Action>>,
+    pub debounce: Option<u32>,
+    pub on_error: Option<Vec<Action>>,
+    #[serde(default)]
     pub placement: Placement,
+    pub visible_when: Option<Condition>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Validation {
+    #[serde(default, deserialize_with = "optional_regex")]
+    pub regex: Option<Regex>,
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    #[serde(default)]
+    pub path_exists: bool,
+    pub message: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct InputNode {
     pub variable: String,
+    pub on_change: Option<Vec<Action>>,
+    pub debounce: Option<u32>,
+    pub on_activate: Option<Vec<Action>>,
+    pub on_error: Option<Vec<Action>>,
+    pub validate: Option<Validation>,
+    pub placeholder: Option<String>,
+    // Expanded after the initialize actions have run
+    pub default: Option<String>,
+    #[serde(default)]
+    pub password: bool,
+    pub max_length: Option<i32>,
+    #[serde(default)]
     pub placement: Placement,
     pub active_when: Option<Condition>,
+    pub visible_when: Option<Condition>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ContainerNode {
     pub name: String,
+    pub scroll: Option<Scroll>,
+    pub max_height: Option<i32>,
+    // Run when the value of options placed in the container changes
+    pub on_change: Option<Vec<Action>>,
+    pub debounce: Option<u32>,
+    pub on_error: Option<Vec<Action>>,
+    #[serde(default)]
+    pub placement: Placement,
+    pub visible_when: Option<Condition>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TableColumn {
+    pub title: String,
+    pub variable: Option<String>,
+    pub key: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TableNode {
+    pub name: String,
+    pub columns: Vec<TableColumn>,
+    pub on_change: Option<Vec<Action>>,
+    pub debounce: Option<u32>,
+    pub on_error: Option<Vec<Action>>,
+    #[serde(default)]
+    pub placement: Placement,
+    pub visible_when: Option<Condition>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BoxNode {
+    pub spacing: Option<i32>,
+    pub nodes: Vec<Node>,
+    pub scroll: Option<Scroll>,
+    pub max_height: Option<i32>,
+    #[serde(default)]
+    pub placement: Placement,
+    pub visible_when: Option<Condition>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GridNode {
+    #[serde(flatten)]
+    pub layout: GridLayout,
+    pub nodes: Vec<Node>,
+    pub scroll: Option<Scroll>,
+    pub max_height: Option<i32>,
+    #[serde(default)]
+    pub placement: Placement,
+    pub visible_when: Option<Condition>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FrameNode {
+    pub title: Option<String>,
+    pub nodes: Vec<Node>,
+    #[serde(default)]
+    pub layout: ConfigLayout,
+    #[serde(default)]
+    pub placement: Placement,
+    pub visible_when: Option<Condition>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum Orientation {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PanedNode {
+    pub orientation: Option<Orientation>,
+    pub position: Option<i32>,
+    pub nodes: Vec<Node>,
+    #[serde(default)]
+    pub placement: Placement,
+    pub visible_when: Option<Condition>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TabPage {
+    pub title: String,
+    pub nodes: Vec<Node>,
+    #[serde(default)]
+    pub layout: ConfigLayout,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TabsNode {
+    pub name: String,
+    pub variable: Option<String>,
+    pub pages: Vec<TabPage>,
+    #[serde(default)]
     pub placement: Placement,
+    pub visible_when: Option<Condition>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -56,16 +604,187 @@ pub struct ContainerNode {
 pub enum Node {
     Button(ButtonNode),
     RadioButtons(RadioButtonsNode),
+    MultiSelect(MultiSelectNode),
     Container(ContainerNode),
     Input(InputNode),
+    Table(TableNode),
+    VBox(BoxNode),
+    HBox(BoxNode),
+    Grid(GridNode),
+    Frame(FrameNode),
+    Paned(PanedNode),
+    Tabs(TabsNode),
+}
+
+impl Node {
+    pub fn children(&self) -> Vec<&Node> {
+        match self {
+            Node::VBox(node) | Node::HBox(node) => node.nodes.iter().collect(),
+            Node::Grid(node) => node.nodes.iter().collect(),
+            Node::Frame(node) => node.nodes.iter().collect(),
+            Node::Paned(node) => node.nodes.iter().collect(),
+            Node::Tabs(node) => node
+                .pages
+                .iter()
+                .flat_map(|page| page.nodes.iter())
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    pub fn active_when(&self) -> Option<&Condition> {
+        match self {
+            Node::Button(btn) => btn.active_when.as_ref(),
+            Node::Input(inp) => inp.active_when.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn on_change(&self) -> Option<&Vec<Action>> {
+        match self {
+            Node::RadioButtons(node) => node.on_change.as_ref(),
+            Node::MultiSelect(node) => node.on_change.as_ref(),
+            Node::Container(node) => node.on_change.as_ref(),
+            Node::Input(node) => node.on_change.as_ref(),
+            Node::Table(node) => node.on_change.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn on_error(&self) -> Option<&Vec<Action>> {
+        match self {
+            Node::Button(node) => node.on_error.as_ref(),
+            Node::RadioButtons(node) => node.on_error.as_ref(),
+            Node::MultiSelect(node) => node.on_error.as_ref(),
+            Node::Container(node) => node.on_error.as_ref(),
+            Node::Input(node) => node.on_error.as_ref(),
+            Node::Table(node) => node.on_error.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn visible_when(&self) -> Option<&Condition> {
+        match self {
+            Node::Button(node) => node.visible_when.as_ref(),
+            Node::RadioButtons(node) => node.visible_when.as_ref(),
+            Node::MultiSelect(node) => node.visible_when.as_ref(),
+            Node::Container(node) => node.visible_when.as_ref(),
+            Node::Input(node) => node.visible_when.as_ref(),
+            Node::Table(node) => node.visible_when.as_ref(),
+            Node::VBox(node) | Node::HBox(node) => node.visible_when.as_ref(),
+            Node::Grid(node) => node.visible_when.as_ref(),
+            Node::Frame(node) => node.visible_when.as_ref(),
+            Node::Paned(node) => node.visible_when.as_ref(),
+            Node::Tabs(node) => node.visible_when.as_ref(),
+        }
+    }
+}
+
+fn collect_nodes<'a>(nodes: Vec<&'a Node>, all: &mut Vec<&'a Node>) {
+    for node in nodes.into_iter() {
+        all.push(node);
+        collect_nodes(node.children(), all);
+    }
 }
 
 // TODO check "bug" where spacing is left out and program panics
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone)]
 pub enum ConfigLayout {
     Vertical { spacing: Option<i32> },
     Horizontal { spacing: Option<i32> },
-    Grid,
+    Grid(Option<GridLayout>),
+}
+
+impl<'de> Deserialize<'de> for ConfigLayout {
+    fn deserialize<D>(deserializer: D) -> Result<ConfigLayout, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(remote = "ConfigLayout")]
+        enum Tagged {
+            Vertical { spacing: Option<i32> },
+            Horizontal { spacing: Option<i32> },
+            Grid(Option<GridLayout>),
+        }
+
+        // `layout: Grid` is short for a grid with the default settings
+        match serde_yaml::Value::deserialize(deserializer)? {
+            serde_yaml::Value::String(ref name) if name == "Grid" => Ok(ConfigLayout::Grid(None)),
+            value => Tagged::deserialize(value).map_err(D::Error::custom),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GridLayout {
+    pub row_homogeneous: Option<bool>,
+    pub column_homogeneous: Option<bool>,
+    pub row_spacing: Option<u32>,
+    pub column_spacing: Option<u32>,
+}
+
+impl Default for ConfigLayout {
+    fn default() -> ConfigLayout {
+        ConfigLayout::Vertical { spacing: None }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WizardPage {
+    pub title: Option<String>,
+    pub nodes: Vec<Node>,
+    #[serde(default)]
+    pub layout: ConfigLayout,
+    pub on_enter: Option<Vec<Action>>,
+    pub on_leave: Option<Vec<Action>>,
+    pub valid_when: Option<Condition>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Timer {
+    // Milliseconds between each time the actions are run
+    pub interval: u32,
+    // Only run the actions once, after the interval
+    #[serde(default)]
+    pub once: bool,
+    pub actions: Vec<Action>,
+    pub active_when: Option<Condition>,
+    pub on_error: Option<Vec<Action>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub enum WatchEvent {
+    Changed,
+    Created,
+    Deleted,
+    Moved,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Watch {
+    // Variables are substituted after the initialize actions have run
+    pub path: String,
+    pub events: Option<Vec<WatchEvent>>,
+    pub debounce: Option<u32>,
+    pub actions: Vec<Action>,
+    pub on_error: Option<Vec<Action>>,
+}
+
+// Map of accelerator: actions, the order doesn't matter but has to stay the same once read
+fn shortcut_map<'de, D>(deserializer: D) -> Result<Vec<(String, Vec<Action>)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(HashMap::<String, Vec<Action>>::deserialize(deserializer)?
+        .into_iter()
+        .collect())
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum WindowPosition {
+    Center,
+    Mouse,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -73,18 +792,251 @@ pub struct Config {
     pub title: String,
     pub width: Option<i32>,
     pub height: Option<i32>,
+    pub resizable: Option<bool>,
+    pub position: Option<WindowPosition>,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    #[serde(default)]
+    pub keep_above: bool,
+    #[serde(default)]
+    pub fullscreen: bool,
+    pub decorated: Option<bool>,
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub modal: bool,
+    #[serde(default)]
     pub nodes: Vec<Node>,
+    #[serde(default)]
     pub layout: ConfigLayout,
     pub initialize: Option<Vec<Action>>,
+    pub pages: Option<Vec<WizardPage>>,
+    pub finish: Option<Vec<Action>>,
+    pub on_error: Option<Vec<Action>>,
+    #[serde(default, deserialize_with = "ordered_map")]
+    pub computed: Vec<(String, String)>,
+    #[serde(default)]
+    pub timers: Vec<Timer>,
+    #[serde(default)]
+    pub watch: Vec<Watch>,
+    #[serde(default, deserialize_with = "shortcut_map")]
+    pub shortcuts: Vec<(String, Vec<Action>)>,
+    #[serde(default)]
+    pub close_on_escape: bool,
+}
+
+impl Config {
+    // All nodes, nested ones included, in the order they are built by the gui.
+    // The index of a node in this list is used to refer to it
+    pub fn all_nodes(&self) -> Vec<&Node> {
+        let mut all = vec![];
+        collect_nodes(self.nodes.iter().collect(), &mut all);
+        for page in self.pages.iter().flatten() {
+            collect_nodes(page.nodes.iter().collect(), &mut all);
+        }
+        all
+    }
+
+    // Every action in the config, nested on_error actions included
+    fn all_actions(&self) -> Vec<&Action> {
+        let mut lists = vec![&self.initialize, &self.finish, &self.on_error];
+        for page in self.pages.iter().flatten() {
+            lists.push(&page.on_enter);
+            lists.push(&page.on_leave);
+        }
+        for timer in self.timers.iter() {
+            lists.push(&timer.on_error);
+        }
+        for watch in self.watch.iter() {
+            lists.push(&watch.on_error);
+        }
+        let mut actions: Vec<&Action> = lists.into_iter().flatten().flatten().collect();
+        for node in self.all_nodes() {
+            match node {
+                Node::Button(node) => actions.extend(node.on_click.iter()),
+                Node::Input(node) => actions.extend(node.on_activate.iter().flatten()),
+                _ => (),
+            }
+            actions.extend(node.on_change().into_iter().flatten());
+            actions.extend(node.on_error().into_iter().flatten());
+        }
+        actions.extend(self.timers.iter().flat_map(|timer| timer.actions.iter()));
+        actions.extend(self.watch.iter().flat_map(|watch| watch.actions.iter()));
+        actions.extend(
+            self.shortcuts
+                .iter()
+                .flat_map(|(_, actions)| actions.iter()),
+        );
+        actions
+    }
+
+    // Catches what can be parsed but not used
+    fn check(&self) -> Result<(), String> {
+        if self.timers.iter().any(|timer| timer.interval == 0) {
+            return Err("the interval of a timer must be more than 0".to_string());
+        }
+        if self
+            .all_actions()
+            .iter()
+            .any(|action| matches!(action, Action::Run { command } if command.is_empty()))
+        {
+            return Err("the command of a Run action can't be empty".to_string());
+        }
+        for node in self.all_nodes() {
+            if let Node::Paned(node) = node {
+                if node.nodes.len() > 2 {
+                    return Err(format!(
+                        "a Paned node can hold at most two nodes, found {}",
+                        node.nodes.len()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Sorts the computed variables so that each one comes after the computed variables it uses,
+    // fails if they depend on each other in a cycle
+    fn sort_computed(&mut self) -> Result<(), String> {
+        fn visit(
+            i: usize,
+            computed: &[(String, String)],
+            visiting: &mut Vec<usize>,
+            sorted: &mut Vec<usize>,
+        ) -> Result<(), String> {
+            if sorted.contains(&i) {
+                return Ok(());
+            }
+            if let Some(start) = visiting.iter().position(|&j| j == i) {
+                let cycle: Vec<&str> = visiting[start..]
+                    .iter()
+                    .chain(std::iter::once(&i))
+                    .map(|&j| computed[j].0.as_str())
+                    .collect();
+                return Err(format!(
+                    "computed variables depend on each other: {}",
+                    cycle.join(" -> ")
+                ));
+            }
+            visiting.push(i);
+            for (j, (name, _)) in computed.iter().enumerate() {
+                if computed[i].1.contains(name.as_str()) {
+                    visit(j, computed, visiting, sorted)?;
+                }
+            }
+            visiting.pop();
+            sorted.push(i);
+            Ok(())
+        }
+
+        let mut sorted = vec![];
+        for i in 0..self.computed.len() {
+            visit(i, &self.computed, &mut vec![], &mut sorted)?;
+        }
+        self.computed = sorted
+            .into_iter()
+            .map(|i| self.computed[i].clone())
+            .collect();
+        Ok(())
+    }
 }
 
 pub fn read_config(filename: &String) -> Result<Config, serde_yaml::Error> {
     debug!("reading config from: {}", filename);
 
-    let config: Config =
+    let mut config: Config =
         serde_yaml::from_str(&fs::read_to_string(filename).expect("could not read config file"))?;
+    config.check().map_err(serde_yaml::Error::custom)?;
+    config.sort_computed().map_err(serde_yaml::Error::custom)?;
 
     debug!("using config:\n{:?}", config);
 
     Ok(config)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn condition(yaml: &str) -> Result<Condition, serde_yaml::Error> {
+        serde_yaml::from_str(yaml)
+    }
+
+    #[test]
+    fn legacy_map_with_negation() {
+        match condition("$A$: yes\n$B$!: no").unwrap() {
+            Condition::All(conditions) => match conditions.as_slice() {
+                [Condition::Equals {
+                    variable: a,
+                    value: a_value,
+                    negate: false,
+                }, Condition::Equals {
+                    variable: b,
+                    value: b_value,
+                    negate: true,
+                }] => {
+                    assert_eq!((a.as_str(), a_value.as_str()), ("$A$", "yes"));
+                    assert_eq!((b.as_str(), b_value.as_str()), ("$B$", "no"));
+                }
+                conditions => panic!("unexpected conditions: {:?}", conditions),
+            },
+            condition => panic!("unexpected condition: {:?}", condition),
+        }
+    }
+
+    #[test]
+    fn nested_any_and_not() {
+        let yaml = "any:\n- $A$: x\n- not:\n    is_empty: $B$\n";
+        match condition(yaml).unwrap() {
+            Condition::Any(conditions) => match conditions.as_slice() {
+                [Condition::Equals { variable, .. }, Condition::Not(not)] => {
+                    assert_eq!(variable, "$A$");
+                    assert!(matches!(**not, Condition::IsEmpty(ref b) if b == "$B$"));
+                }
+                conditions => panic!("unexpected conditions: {:?}", conditions),
+            },
+            condition => panic!("unexpected condition: {:?}", condition),
+        }
+    }
+
+    #[test]
+    fn bad_regex_is_an_error() {
+        assert!(condition("matches:\n  $A$: '('").is_err());
+    }
+
+    #[test]
+    fn comparing_with_a_non_number_is_an_error() {
+        assert!(condition("lt:\n  $A$: ten").is_err());
+        assert!(condition("gt:\n  $A$: ten").is_err());
+    }
+
+    fn sort_computed(computed: &str) -> Result<Vec<String>, String> {
+        let yaml = format!("title: test\nnodes: []\ncomputed:\n{}", computed);
+        let mut config: Config = serde_yaml::from_str(&yaml).unwrap();
+        config.sort_computed()?;
+        Ok(config.computed.into_iter().map(|(name, _)| name).collect())
+    }
+
+    #[test]
+    fn computed_self_reference_is_an_error() {
+        let err = sort_computed("  $A$: $A$/x\n").unwrap_err();
+        assert!(err.ends_with("$A$ -> $A$"), "{}", err);
+    }
+
+    #[test]
+    fn computed_cycle_is_an_error() {
+        let err = sort_computed("  $A$: $B$/x\n  $B$: $A$/y\n").unwrap_err();
+        assert!(err.ends_with("$A$ -> $B$ -> $A$"), "{}", err);
+    }
+
+    #[test]
+    fn computed_chain_in_reverse_order_is_sorted() {
+        let sorted = sort_computed("  $C$: $B$/c\n  $B$: $A$/b\n  $A$: a\n").unwrap();
+        assert_eq!(sorted, vec!["$A$", "$B$", "$C$"]);
+    }
+
+    #[test]
+    fn keyword_with_a_value_is_an_error() {
+        assert!(condition("any: yes").is_err());
+        assert!(condition("not: 1").is_err());
+    }
+}
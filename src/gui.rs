@@ -1,8 +1,11 @@
 use super::config::*;
 use super::handler::*;
+use gio::prelude::*;
 use gtk::prelude::*;
-use gtk::{Application, ApplicationWindow, Button, RadioButton};
+use gtk::{Application, ApplicationWindow, Button, CheckButton, RadioButton};
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 use std::sync::mpsc;
 
 #[derive(Debug)]
@@ -10,20 +13,174 @@ pub enum MsgGui {
     Show {
         container: String,
         text: String,
+        scroll: Option<Scroll>,
+        max_height: Option<i32>,
     },
     Options {
         container: String,
         variable: String,
         options: Vec<(String, String)>,
+        selected: Option<String>,
     },
+    MultiSelect {
+        container: String,
+        variable: String,
+        options: Vec<(String, String)>,
+        selected: Vec<String>,
+        separator: String,
+    },
+    // Replies once the image is shown, or with why it could not be loaded
     Image {
         container: String,
         filename: String,
+        reply: usize,
     },
     SetActive {
         node: usize,
         active: bool,
     },
+    SetVisible {
+        node: usize,
+        visible: bool,
+    },
+    SetText {
+        node: usize,
+        text: String,
+    },
+    Watch {
+        watch: usize,
+        path: String,
+    },
+    SetTitle {
+        title: String,
+    },
+    // Dialogs, the actions after them wait for a MsgHandler::Reply with the reply id
+    Message {
+        text: String,
+        error: bool,
+        reply: usize,
+    },
+    Confirm {
+        text: String,
+        reply: usize,
+    },
+    Prompt {
+        text: String,
+        password: bool,
+        reply: usize,
+    },
+    Table {
+        table: String,
+        rows: Vec<Vec<String>>,
+    },
+    SetTab {
+        tabs: String,
+        page: u32,
+    },
+    SetPage {
+        page: usize,
+        valid: bool,
+    },
+    SetPageValid {
+        valid: bool,
+    },
+    ShowError {
+        text: String,
+    },
+    Notify {
+        title: String,
+        body: Option<String>,
+        icon: Option<String>,
+        urgency: Option<Urgency>,
+    },
+    // Replies once copied, or fails if there is no clipboard
+    CopyText {
+        text: String,
+        reply: usize,
+    },
+    CopyImage {
+        filename: String,
+        reply: usize,
+    },
+    Paste {
+        reply: usize,
+    },
+    // Replies after the time has passed
+    Sleep {
+        ms: u32,
+        reply: usize,
+    },
+    Close,
+}
+
+fn gtk_align(align: &Align) -> gtk::Align {
+    match align {
+        Align::Fill => gtk::Align::Fill,
+        Align::Start => gtk::Align::Start,
+        Align::End => gtk::Align::End,
+        Align::Center => gtk::Align::Center,
+        Align::Baseline => gtk::Align::Baseline,
+    }
+}
+
+// Wraps the widget in a scrolled window if scrolling is enabled, so long content
+// doesn't make the window grow off screen
+fn scrolled(widget: gtk::Widget, scroll: &Option<Scroll>, max_height: Option<i32>) -> gtk::Widget {
+    let (h, v) = match scroll {
+        Some(Scroll::Horizontal) => (gtk::PolicyType::Automatic, gtk::PolicyType::Never),
+        Some(Scroll::Vertical) => (gtk::PolicyType::Never, gtk::PolicyType::Automatic),
+        Some(Scroll::Both) => (gtk::PolicyType::Automatic, gtk::PolicyType::Automatic),
+        None => return widget,
+    };
+    let window = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    window.set_policy(h, v);
+    // Asks for the size of the content on the scrolled axes, instead of just enough for the
+    // scrollbar, so it only scrolls once there is no room or it grows past max_height
+    window.set_propagate_natural_width(h != gtk::PolicyType::Never);
+    window.set_propagate_natural_height(v != gtk::PolicyType::Never);
+    if let Some(max_height) = max_height {
+        window.set_max_content_height(max_height);
+    }
+    window.add(&widget);
+    window.upcast::<gtk::Widget>()
+}
+
+// The clipboard of the display the window is on
+fn window_clipboard(window: &ApplicationWindow) -> Option<gtk::Clipboard> {
+    window
+        .get_display()
+        .and_then(|display| gtk::Clipboard::get_default(&display))
+}
+
+// An icon can be a path to a file or the name of an icon in the icon theme
+fn icon_is_path(icon: &str) -> bool {
+    icon.contains('/')
+}
+
+// Sends a desktop notification through the application
+fn send_notification(
+    app: &Application,
+    title: &str,
+    body: Option<&str>,
+    icon: Option<&str>,
+    urgency: Option<&Urgency>,
+) {
+    let notification = gio::Notification::new(title);
+    notification.set_body(body);
+    match icon {
+        Some(icon) if icon_is_path(icon) => {
+            notification.set_icon(&gio::FileIcon::new(&gio::File::new_for_path(icon)))
+        }
+        Some(icon) => notification.set_icon(&gio::ThemedIcon::new(icon)),
+        None => (),
+    }
+    notification.set_priority(match urgency {
+        Some(Urgency::Low) => gio::NotificationPriority::Low,
+        Some(Urgency::Normal) | None => gio::NotificationPriority::Normal,
+        Some(Urgency::High) => gio::NotificationPriority::High,
+        Some(Urgency::Urgent) => gio::NotificationPriority::Urgent,
+    });
+    app.send_notification(None, &notification);
 }
 
 enum Layout {
@@ -31,17 +188,196 @@ enum Layout {
     Grid(gtk::Grid),
 }
 
+type Changed = Option<Rc<dyn Fn()>>;
+
+// Sends a message to the handler each time it is called. With a debounce, the message is
+// sent when it hasn't been called for that long
+fn debounced<F>(msg: F, debounce: Option<u32>, tx: &mpsc::Sender<MsgHandler>) -> Rc<dyn Fn()>
+where
+    F: Fn() -> MsgHandler + 'static,
+{
+    let tx = tx.clone();
+    match debounce {
+        None | Some(0) => Rc::new(move || tx.send(msg()).unwrap()),
+        Some(debounce) => {
+            let msg = Rc::new(msg);
+            let pending: Rc<RefCell<Option<glib::SourceId>>> = Rc::new(RefCell::new(None));
+            Rc::new(move || {
+                if let Some(source) = pending.borrow_mut().take() {
+                    glib::source_remove(source);
+                }
+                let tx = tx.clone();
+                let msg = msg.clone();
+                let pending_clone = pending.clone();
+                let source = glib::timeout_add_local(debounce, move || {
+                    pending_clone.borrow_mut().take();
+                    tx.send(msg()).unwrap();
+                    glib::Continue(false)
+                });
+                pending.borrow_mut().replace(source);
+            })
+        }
+    }
+}
+
+// Tells the handler that the value of a node has changed, if the node has on_change actions
+fn change_notifier(
+    i: usize,
+    on_change: &Option<Vec<Action>>,
+    debounce: Option<u32>,
+    tx: &mpsc::Sender<MsgHandler>,
+) -> Changed {
+    on_change.as_ref()?;
+    Some(debounced(move || MsgHandler::Change(i), debounce, tx))
+}
+
+// Runs the actions of a watch when the file changes, if the event is one it watches for
+fn watch_file(
+    i: usize,
+    watch: &Watch,
+    path: &str,
+    tx: &mpsc::Sender<MsgHandler>,
+) -> Result<gio::FileMonitor, String> {
+    let monitor = match gio::File::new_for_path(path).monitor(
+        gio::FileMonitorFlags::WATCH_MOVES,
+        None::<&gio::Cancellable>,
+    ) {
+        Ok(monitor) => monitor,
+        Err(err) => return Err(format!("could not watch {}: {}", path, err)),
+    };
+    let events = watch.events.clone().unwrap_or_else(|| {
+        vec![
+            WatchEvent::Changed,
+            WatchEvent::Created,
+            WatchEvent::Deleted,
+            WatchEvent::Moved,
+        ]
+    });
+    let changed = debounced(move || MsgHandler::Watch(i), watch.debounce, tx);
+    monitor.connect_changed(move |_, _, _, event| {
+        let event = match event {
+            // Sent once a write is done, after any number of Changed events
+            gio::FileMonitorEvent::ChangesDoneHint => WatchEvent::Changed,
+            gio::FileMonitorEvent::Created => WatchEvent::Created,
+            gio::FileMonitorEvent::Deleted => WatchEvent::Deleted,
+            gio::FileMonitorEvent::Moved
+            | gio::FileMonitorEvent::Renamed
+            | gio::FileMonitorEvent::MovedIn
+            | gio::FileMonitorEvent::MovedOut => WatchEvent::Moved,
+            _ => return,
+        };
+        if events.contains(&event) {
+            changed();
+        }
+    });
+    Ok(monitor)
+}
+
+// Returns the first rule the text breaks
+fn validation_error(rules: &Validation, text: &str) -> Option<String> {
+    let length = text.chars().count();
+    if let Some(regex) = &rules.regex {
+        if !regex.is_match(text) {
+            return Some("has the wrong format".to_string());
+        }
+    }
+    if let Some(min) = rules.min_length {
+        if length < min {
+            return Some(format!("must be at least {} characters", min));
+        }
+    }
+    if let Some(max) = rules.max_length {
+        if length > max {
+            return Some(format!("must be at most {} characters", max));
+        }
+    }
+    if rules.min.is_some() || rules.max.is_some() {
+        let number = match text.trim().parse::<f64>() {
+            Ok(number) => number,
+            Err(_) => return Some("must be a number".to_string()),
+        };
+        if let Some(min) = rules.min {
+            if number < min {
+                return Some(format!("must be at least {}", min));
+            }
+        }
+        if let Some(max) = rules.max {
+            if number > max {
+                return Some(format!("must be at most {}", max));
+            }
+        }
+    }
+    if rules.path_exists && !std::path::Path::new(text).exists() {
+        return Some("must be an existing path".to_string());
+    }
+    None
+}
+
+fn validate(rules: &Validation, text: &str) -> Result<(), String> {
+    match validation_error(rules, text) {
+        Some(error) => Err(rules.message.clone().unwrap_or(error)),
+        None => Ok(()),
+    }
+}
+
+// $NAME$ becomes $NAME_VALID$, other names get _VALID appended
+fn valid_variable(variable: &str) -> String {
+    if variable.len() > 1 && variable.starts_with('$') && variable.ends_with('$') {
+        format!("{}_VALID$", &variable[..variable.len() - 1])
+    } else {
+        format!("{}_VALID", variable)
+    }
+}
+
+// Sets the valid variable of an input, and shows the error on the input if show_error is set
+fn send_valid(
+    input: &gtk::Entry,
+    rules: &Validation,
+    variable: &str,
+    show_error: bool,
+    tx: &mpsc::Sender<MsgHandler>,
+) {
+    let result = validate(rules, &input.get_buffer().get_text());
+    tx.send(MsgHandler::Var {
+        variable: valid_variable(variable),
+        value: if result.is_ok() { "yes" } else { "no" }.to_string(),
+    })
+    .unwrap();
+    if !show_error {
+        return;
+    }
+    let style = input.get_style_context();
+    match result {
+        Ok(()) => {
+            style.remove_class("error");
+            input.set_tooltip_text(None);
+            input.set_icon_from_icon_name(gtk::EntryIconPosition::Secondary, None);
+        }
+        Err(error) => {
+            style.add_class("error");
+            input.set_tooltip_text(Some(&error));
+            input.set_icon_from_icon_name(
+                gtk::EntryIconPosition::Secondary,
+                Some("dialog-error-symbolic"),
+            );
+            input.set_icon_tooltip_text(gtk::EntryIconPosition::Secondary, Some(&error));
+        }
+    }
+}
+
 fn create_radio_buttons(
     btns: Vec<(&String, &String)>,
+    selected: Option<&String>,
     var: String,
+    changed: Changed,
     tx: mpsc::Sender<MsgHandler>,
 ) -> gtk::Widget {
     let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
-    let mut group: Option<(String, RadioButton)> = None;
+    let mut buttons: Vec<(String, RadioButton)> = vec![];
     for (value, label) in btns.iter() {
         let button = RadioButton::new_with_label(label);
         let tx = tx.clone();
-        let value_clone = value.clone().to_owned();
+        let value_clone = value.to_string();
         let var = var.clone();
         button.connect_toggled(move |btn| {
             if btn.get_active() {
@@ -53,133 +389,677 @@ fn create_radio_buttons(
             }
         });
         container.pack_start(&button, false, false, 0);
-        if let Some((_, group)) = group.clone() {
-            button.join_group(Some(&group));
-        } else {
-            group = Some((value.clone().to_owned(), button.clone()));
+        if let Some((_, group)) = buttons.first() {
+            button.join_group(Some(group));
         }
+        buttons.push((value.to_string(), button));
     }
-    if let Some((value, _)) = group.clone() {
+    let active = selected
+        .and_then(|selected| buttons.iter().find(|(value, _)| value == selected))
+        .or_else(|| buttons.first());
+    if let Some((value, button)) = active {
+        button.set_active(true);
         tx.send(MsgHandler::Var {
             variable: var,
             value: value.clone(),
         })
         .unwrap();
     }
+    // Connected after the initial selection, which isn't a change
+    if let Some(changed) = changed {
+        for (_, button) in buttons.iter() {
+            let changed = changed.clone();
+            button.connect_toggled(move |btn| {
+                if btn.get_active() {
+                    changed();
+                }
+            });
+        }
+    }
     container.upcast::<gtk::Widget>()
 }
 
-pub fn setup_gui(
-    tx: mpsc::Sender<MsgHandler>,
-    grx: glib::Receiver<MsgGui>,
-    config: &Config,
-    app: &Application,
+fn send_checked(
+    buttons: &[(String, CheckButton)],
+    var: &str,
+    separator: &str,
+    tx: &mpsc::Sender<MsgHandler>,
 ) {
-    let window = ApplicationWindow::new(app);
-    window.set_title(&config.title);
-    window.set_default_size(config.width.unwrap_or(600), config.height.unwrap_or(600));
+    tx.send(MsgHandler::List {
+        variable: var.to_string(),
+        values: buttons
+            .iter()
+            .filter(|(_, button)| button.get_active())
+            .map(|(value, _)| value.clone())
+            .collect(),
+        separator: separator.to_string(),
+    })
+    .unwrap();
+}
 
-    let mut containers = HashMap::new();
-    let mut conditionals = HashMap::new();
-
-    let layout = match config.layout {
-        ConfigLayout::Vertical { spacing } => Layout::Box(gtk::Box::new(
-            gtk::Orientation::Vertical,
-            spacing.unwrap_or(0),
-        )),
-        ConfigLayout::Horizontal { spacing } => Layout::Box(gtk::Box::new(
-            gtk::Orientation::Horizontal,
-            spacing.unwrap_or(0),
-        )),
-        ConfigLayout::Grid => {
-            let grid = gtk::Grid::new();
-            grid.set_row_homogeneous(true);
-            grid.set_column_homogeneous(true);
-            Layout::Grid(grid)
-        }
-    };
-    for (i, node) in config.nodes.iter().enumerate() {
-        let (n, p) = match node {
-            Node::Button(btn) => {
-                let button = Button::new_with_label(&btn.text);
-                let tx = tx.clone();
-                button.connect_clicked(move |_| {
-                    tx.send(MsgHandler::Action(i)).unwrap();
-                });
-                if btn.active_when.is_some() {
-                    conditionals.insert(i, button.clone().upcast::<gtk::Widget>());
-                }
-                (button.upcast::<gtk::Widget>(), &btn.placement)
+fn create_check_buttons(
+    btns: Vec<(&String, &String)>,
+    selected: &[String],
+    var: String,
+    separator: String,
+    changed: Changed,
+    tx: mpsc::Sender<MsgHandler>,
+) -> gtk::Widget {
+    let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    let buttons: Rc<Vec<(String, CheckButton)>> = Rc::new(
+        btns.iter()
+            .map(|(value, label)| {
+                let button = CheckButton::new_with_label(label);
+                button.set_active(selected.contains(*value));
+                container.pack_start(&button, false, false, 0);
+                (value.to_string(), button)
+            })
+            .collect(),
+    );
+    for (_, button) in buttons.iter() {
+        let buttons = buttons.clone();
+        let var = var.clone();
+        let separator = separator.clone();
+        let changed = changed.clone();
+        let tx = tx.clone();
+        button.connect_toggled(move |_| {
+            send_checked(&buttons, &var, &separator, &tx);
+            if let Some(changed) = &changed {
+                changed();
             }
-            Node::RadioButtons(btns) => {
-                let container = create_radio_buttons(
-                    btns.options.iter().collect(),
-                    btns.variable.clone(),
-                    tx.clone(),
+        });
+    }
+    send_checked(&buttons, &var, &separator, &tx);
+    container.upcast::<gtk::Widget>()
+}
+
+fn create_table(
+    table: &TableNode,
+    changed: Changed,
+    tx: mpsc::Sender<MsgHandler>,
+) -> (gtk::Widget, gtk::ListStore) {
+    let store = gtk::ListStore::new(&vec![glib::Type::String; table.columns.len()]);
+    let tree = gtk::TreeView::new_with_model(&store);
+    for (i, column) in table.columns.iter().enumerate() {
+        let cell = gtk::CellRendererText::new();
+        let tree_column = gtk::TreeViewColumn::new();
+        tree_column.set_title(&column.title);
+        tree_column.pack_start(&cell, true);
+        tree_column.add_attribute(&cell, "text", i as i32);
+        tree_column.set_sort_column_id(i as i32);
+        tree_column.set_resizable(true);
+        tree.append_column(&tree_column);
+    }
+    let variables: Vec<(i32, String)> = table
+        .columns
+        .iter()
+        .enumerate()
+        .filter_map(|(i, column)| column.variable.clone().map(|var| (i as i32, var)))
+        .collect();
+    tree.get_selection().connect_changed(move |selection| {
+        if let Some((model, iter)) = selection.get_selected() {
+            for (i, variable) in variables.iter() {
+                let value = model.get_value(&iter, *i).get::<String>();
+                tx.send(MsgHandler::Var {
+                    variable: variable.clone(),
+                    value: value.ok().and_then(|value| value).unwrap_or_default(),
+                })
+                .unwrap();
+            }
+            if let Some(changed) = &changed {
+                changed();
+            }
+        }
+    });
+    (tree.upcast::<gtk::Widget>(), store)
+}
+
+struct Widgets {
+    containers: HashMap<String, gtk::Box>,
+    // Change notifiers for options placed in containers
+    changes: HashMap<String, Rc<dyn Fn()>>,
+    tables: HashMap<String, gtk::ListStore>,
+    tabs: HashMap<String, gtk::Notebook>,
+    inputs: HashMap<usize, gtk::Entry>,
+    accelerators: Vec<(String, Button)>,
+    conditionals: HashMap<usize, gtk::Widget>,
+}
+
+impl Layout {
+    fn new(layout: &ConfigLayout) -> Layout {
+        match layout {
+            ConfigLayout::Vertical { spacing } => Layout::Box(gtk::Box::new(
+                gtk::Orientation::Vertical,
+                spacing.unwrap_or(0),
+            )),
+            ConfigLayout::Horizontal { spacing } => Layout::Box(gtk::Box::new(
+                gtk::Orientation::Horizontal,
+                spacing.unwrap_or(0),
+            )),
+            ConfigLayout::Grid(layout) => {
+                let grid = gtk::Grid::new();
+                let layout = layout.as_ref();
+                grid.set_row_homogeneous(layout.and_then(|l| l.row_homogeneous).unwrap_or(true));
+                grid.set_column_homogeneous(
+                    layout.and_then(|l| l.column_homogeneous).unwrap_or(true),
                 );
-                (container, &btns.placement)
+                grid.set_row_spacing(layout.and_then(|l| l.row_spacing).unwrap_or(0));
+                grid.set_column_spacing(layout.and_then(|l| l.column_spacing).unwrap_or(0));
+                Layout::Grid(grid)
+            }
+        }
+    }
+
+    fn attach(&self, n: &gtk::Widget, p: &Placement) {
+        let expand = p.expand.unwrap_or(false);
+        match self {
+            Layout::Box(container) => {
+                container.pack_start(n, expand, p.fill.unwrap_or(true), p.spacing.unwrap_or(0))
+            }
+            Layout::Grid(container) => {
+                n.set_hexpand(expand);
+                n.set_vexpand(expand);
+                container.attach(
+                    n,
+                    p.x.unwrap_or(0),
+                    p.y.unwrap_or(0),
+                    p.w.unwrap_or(1),
+                    p.h.unwrap_or(1),
+                )
+            }
+        };
+        if let Some(halign) = &p.halign {
+            n.set_halign(gtk_align(halign));
+        }
+        if let Some(valign) = &p.valign {
+            n.set_valign(gtk_align(valign));
+        }
+        let margin = p.margin.unwrap_or(0);
+        n.set_margin_top(p.margin_top.unwrap_or(margin));
+        n.set_margin_bottom(p.margin_bottom.unwrap_or(margin));
+        n.set_margin_start(p.margin_start.unwrap_or(margin));
+        n.set_margin_end(p.margin_end.unwrap_or(margin));
+        n.set_size_request(p.min_width.unwrap_or(-1), p.min_height.unwrap_or(-1));
+    }
+
+    fn into_widget(self) -> gtk::Widget {
+        match self {
+            Layout::Box(container) => container.upcast::<gtk::Widget>(),
+            Layout::Grid(container) => container.upcast::<gtk::Widget>(),
+        }
+    }
+}
+
+// Nodes get their index in the order they are built, which has to match Config::all_nodes
+fn build_node<'a>(
+    node: &'a Node,
+    next: &mut usize,
+    widgets: &mut Widgets,
+    tx: &mpsc::Sender<MsgHandler>,
+) -> (gtk::Widget, &'a Placement) {
+    let i = *next;
+    *next += 1;
+    let (widget, placement) = build_widget(node, i, next, widgets, tx);
+    if node.active_when().is_some() || node.visible_when().is_some() {
+        widgets.conditionals.insert(i, widget.clone());
+    }
+    (widget, placement)
+}
+
+fn build_widget<'a>(
+    node: &'a Node,
+    i: usize,
+    next: &mut usize,
+    widgets: &mut Widgets,
+    tx: &mpsc::Sender<MsgHandler>,
+) -> (gtk::Widget, &'a Placement) {
+    match node {
+        Node::Button(btn) => {
+            // The mnemonic is pressed together with Alt
+            let button = if btn.mnemonic {
+                Button::new_with_mnemonic(&btn.text)
+            } else {
+                Button::new_with_label(&btn.text)
+            };
+            let tx = tx.clone();
+            button.connect_clicked(move |_| {
+                tx.send(MsgHandler::Action(i)).unwrap();
+            });
+            if let Some(accelerator) = &btn.accelerator {
+                widgets
+                    .accelerators
+                    .push((accelerator.clone(), button.clone()));
+            }
+            (button.upcast::<gtk::Widget>(), &btn.placement)
+        }
+        Node::RadioButtons(btns) => {
+            let container = create_radio_buttons(
+                btns.options.iter().map(|(a, b)| (a, b)).collect(),
+                btns.default.as_ref(),
+                btns.variable.clone(),
+                change_notifier(i, &btns.on_change, btns.debounce, tx),
+                tx.clone(),
+            );
+            (container, &btns.placement)
+        }
+        Node::MultiSelect(select) => {
+            let container = create_check_buttons(
+                select.options.iter().map(|(a, b)| (a, b)).collect(),
+                &select.default,
+                select.variable.clone(),
+                select.separator.clone().unwrap_or_else(|| "\n".to_string()),
+                change_notifier(i, &select.on_change, select.debounce, tx),
+                tx.clone(),
+            );
+            (container, &select.placement)
+        }
+        Node::Container(cont) => {
+            let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
+            let w = scrolled(
+                container.clone().upcast::<gtk::Widget>(),
+                &cont.scroll,
+                cont.max_height,
+            );
+            widgets.containers.insert(cont.name.clone(), container);
+            if let Some(changed) = change_notifier(i, &cont.on_change, cont.debounce, tx) {
+                widgets.changes.insert(cont.name.clone(), changed);
             }
-            Node::Container(cont) => {
-                let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
-                let w = container.clone().upcast::<gtk::Widget>();
-                containers.insert(cont.name.clone(), container);
-                (w, &cont.placement)
+            (w, &cont.placement)
+        }
+        Node::Input(inp) => {
+            let input = gtk::Entry::new();
+            if let Some(placeholder) = &inp.placeholder {
+                input.set_placeholder_text(Some(placeholder));
+            }
+            if inp.password {
+                input.set_visibility(false);
+                input.set_input_purpose(gtk::InputPurpose::Password);
+            }
+            if let Some(max_length) = inp.max_length {
+                input.set_max_length(max_length);
+            }
+            widgets.inputs.insert(i, input.clone());
+            tx.send(MsgHandler::Var {
+                variable: inp.variable.clone(),
+                value: "".to_string(),
+            })
+            .unwrap();
+            if inp.on_activate.is_some() {
+                let tx = tx.clone();
+                input.connect_activate(move |_| tx.send(MsgHandler::Activate(i)).unwrap());
             }
-            Node::Input(inp) => {
-                let input = gtk::Entry::new();
+            // The error is not shown until the text is changed, but the valid variable is set
+            if let Some(rules) = &inp.validate {
+                send_valid(&input, rules, &inp.variable, false, tx);
+            }
+            let changed = change_notifier(i, &inp.on_change, inp.debounce, tx);
+            let rules = inp.validate.clone();
+            let tx = tx.clone();
+            let variable = inp.variable.clone();
+            input.connect_changed(move |input| {
                 tx.send(MsgHandler::Var {
-                    variable: inp.variable.clone(),
-                    value: "".to_string(),
+                    variable: variable.clone(),
+                    value: input.get_buffer().get_text(),
                 })
                 .unwrap();
+                if let Some(rules) = &rules {
+                    send_valid(input, rules, &variable, true, &tx);
+                }
+                if let Some(changed) = &changed {
+                    changed();
+                }
+            });
+            (input.upcast::<gtk::Widget>(), &inp.placement)
+        }
+        Node::Table(table) => {
+            let changed = change_notifier(i, &table.on_change, table.debounce, tx);
+            let (tree, store) = create_table(table, changed, tx.clone());
+            widgets.tables.insert(table.name.clone(), store);
+            (tree, &table.placement)
+        }
+        Node::VBox(node) => {
+            let layout = ConfigLayout::Vertical {
+                spacing: node.spacing,
+            };
+            let container = build_nodes(&node.nodes, &layout, next, widgets, tx);
+            (
+                scrolled(container, &node.scroll, node.max_height),
+                &node.placement,
+            )
+        }
+        Node::HBox(node) => {
+            let layout = ConfigLayout::Horizontal {
+                spacing: node.spacing,
+            };
+            let container = build_nodes(&node.nodes, &layout, next, widgets, tx);
+            (
+                scrolled(container, &node.scroll, node.max_height),
+                &node.placement,
+            )
+        }
+        Node::Grid(node) => {
+            let layout = ConfigLayout::Grid(Some(node.layout.clone()));
+            let container = build_nodes(&node.nodes, &layout, next, widgets, tx);
+            (
+                scrolled(container, &node.scroll, node.max_height),
+                &node.placement,
+            )
+        }
+        Node::Frame(node) => {
+            let frame = gtk::Frame::new(node.title.as_deref());
+            frame.add(&build_nodes(&node.nodes, &node.layout, next, widgets, tx));
+            (frame.upcast::<gtk::Widget>(), &node.placement)
+        }
+        Node::Paned(node) => {
+            let paned = gtk::Paned::new(match node.orientation {
+                Some(Orientation::Vertical) => gtk::Orientation::Vertical,
+                _ => gtk::Orientation::Horizontal,
+            });
+            for (j, child) in node.nodes.iter().enumerate() {
+                let (child, _) = build_node(child, next, widgets, tx);
+                if j == 0 {
+                    paned.pack1(&child, true, false);
+                } else {
+                    paned.pack2(&child, true, false);
+                }
+            }
+            if let Some(position) = node.position {
+                paned.set_position(position);
+            }
+            (paned.upcast::<gtk::Widget>(), &node.placement)
+        }
+        Node::Tabs(node) => {
+            let notebook = gtk::Notebook::new();
+            for page in node.pages.iter() {
+                let content = build_nodes(&page.nodes, &page.layout, next, widgets, tx);
+                notebook.append_page(&content, Some(&gtk::Label::new(Some(&page.title))));
+            }
+            if let Some(variable) = &node.variable {
+                let titles: Vec<String> =
+                    node.pages.iter().map(|page| page.title.clone()).collect();
+                if let Some(title) = titles.first() {
+                    tx.send(MsgHandler::Var {
+                        variable: variable.clone(),
+                        value: title.clone(),
+                    })
+                    .unwrap();
+                }
                 let tx = tx.clone();
-                let variable = inp.variable.clone();
-                input.connect_changed(move |input| {
+                let variable = variable.clone();
+                notebook.connect_switch_page(move |_, _, page| {
                     tx.send(MsgHandler::Var {
                         variable: variable.clone(),
-                        value: input.get_buffer().get_text(),
+                        value: titles[page as usize].clone(),
                     })
                     .unwrap();
                 });
-                if inp.active_when.is_some() {
-                    conditionals.insert(i, input.clone().upcast::<gtk::Widget>());
-                }
+            }
+            widgets.tabs.insert(node.name.clone(), notebook.clone());
+            (notebook.upcast::<gtk::Widget>(), &node.placement)
+        }
+    }
+}
+
+fn build_nodes(
+    nodes: &[Node],
+    layout: &ConfigLayout,
+    next: &mut usize,
+    widgets: &mut Widgets,
+    tx: &mpsc::Sender<MsgHandler>,
+) -> gtk::Widget {
+    let layout = Layout::new(layout);
+    for node in nodes.iter() {
+        let (n, p) = build_node(node, next, widgets, tx);
+        layout.attach(&n, p);
+    }
+    layout.into_widget()
+}
+
+struct Wizard {
+    notebook: gtk::Notebook,
+    back: Button,
+    next: Button,
+    finish: Button,
+    pages: usize,
+}
 
-                (input.upcast::<gtk::Widget>(), &inp.placement)
+fn build_wizard(
+    pages: &[WizardPage],
+    next: &mut usize,
+    widgets: &mut Widgets,
+    tx: &mpsc::Sender<MsgHandler>,
+) -> (gtk::Widget, Wizard) {
+    let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    let notebook = gtk::Notebook::new();
+    notebook.set_show_tabs(false);
+    for page in pages.iter() {
+        let content = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        if let Some(title) = &page.title {
+            content.pack_start(&gtk::Label::new(Some(title)), false, false, 4);
+        }
+        let nodes = build_nodes(&page.nodes, &page.layout, next, widgets, tx);
+        content.pack_start(&nodes, true, true, 0);
+        notebook.append_page(&content, None::<&gtk::Widget>);
+    }
+    container.pack_start(&notebook, true, true, 0);
+
+    let buttons = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+    let back = Button::new_with_label("Back");
+    let next_button = Button::new_with_label("Next");
+    let finish = Button::new_with_label("Finish");
+    let tx_back = tx.clone();
+    back.connect_clicked(move |_| tx_back.send(MsgHandler::PreviousPage).unwrap());
+    let tx_next = tx.clone();
+    next_button.connect_clicked(move |_| tx_next.send(MsgHandler::NextPage).unwrap());
+    let tx_finish = tx.clone();
+    finish.connect_clicked(move |_| tx_finish.send(MsgHandler::Finish).unwrap());
+    // Shown or hidden depending on the current page when it is set
+    next_button.set_no_show_all(true);
+    finish.set_no_show_all(true);
+    back.set_sensitive(false);
+    buttons.pack_start(&back, false, false, 0);
+    buttons.pack_end(&finish, false, false, 0);
+    buttons.pack_end(&next_button, false, false, 0);
+    container.pack_start(&buttons, false, false, 0);
+
+    let wizard = Wizard {
+        notebook,
+        back,
+        next: next_button,
+        finish,
+        pages: pages.len(),
+    };
+    (container.upcast::<gtk::Widget>(), wizard)
+}
+
+// Runs f when the accelerator is pressed in the window
+fn add_shortcut<F>(
+    window: &ApplicationWindow,
+    app: &Application,
+    name: &str,
+    accelerator: &str,
+    f: F,
+) where
+    F: Fn() + 'static,
+{
+    let (key, _) = gtk::accelerator_parse(accelerator);
+    if key == 0 {
+        warn!("could not parse accelerator {}", accelerator);
+        return;
+    }
+    let action = gio::SimpleAction::new(name, None);
+    action.connect_activate(move |_, _| f());
+    window.add_action(&action);
+    app.set_accels_for_action(&format!("win.{}", name), &[accelerator]);
+}
+
+fn set_window_properties(window: &ApplicationWindow, config: &Config) {
+    if let Some(resizable) = config.resizable {
+        window.set_resizable(resizable);
+    }
+    match config.position {
+        Some(WindowPosition::Center) => window.set_position(gtk::WindowPosition::Center),
+        Some(WindowPosition::Mouse) => window.set_position(gtk::WindowPosition::Mouse),
+        None => (),
+    }
+    // Not all window managers let windows place themselves, like on wayland
+    if config.x.is_some() || config.y.is_some() {
+        window.move_(config.x.unwrap_or(0), config.y.unwrap_or(0));
+    }
+    window.set_keep_above(config.keep_above);
+    if config.fullscreen {
+        window.fullscreen();
+    }
+    if let Some(decorated) = config.decorated {
+        window.set_decorated(decorated);
+    }
+    match &config.icon {
+        Some(icon) if icon_is_path(icon) => {
+            if let Err(err) = window.set_icon_from_file(icon) {
+                warn!("could not load icon {}: {}", icon, err);
             }
-        };
-        match &layout {
-            Layout::Box(container) => {
-                container.pack_start(&n, false, false, p.spacing.unwrap_or(0))
-            }
-            Layout::Grid(container) => container.attach(
-                &n,
-                p.x.unwrap_or(0),
-                p.y.unwrap_or(0),
-                p.w.unwrap_or(1),
-                p.h.unwrap_or(1),
-            ),
-        };
+        }
+        Some(icon) => window.set_icon_name(Some(icon)),
+        None => (),
+    }
+    // Shown like a dialog, kept out of the task bar and floating in tiling window managers
+    if config.modal {
+        window.set_modal(true);
+        window.set_type_hint(gdk::WindowTypeHint::Dialog);
+        window.set_skip_taskbar_hint(true);
+        window.set_skip_pager_hint(true);
+    }
+}
+
+pub fn setup_gui(
+    tx: mpsc::Sender<MsgHandler>,
+    grx: glib::Receiver<MsgGui>,
+    config: &Config,
+    app: &Application,
+) {
+    let window = ApplicationWindow::new(app);
+    window.set_title(&config.title);
+    window.set_default_size(config.width.unwrap_or(600), config.height.unwrap_or(600));
+    set_window_properties(&window, config);
+
+    let mut widgets = Widgets {
+        containers: HashMap::new(),
+        changes: HashMap::new(),
+        tables: HashMap::new(),
+        tabs: HashMap::new(),
+        inputs: HashMap::new(),
+        accelerators: vec![],
+        conditionals: HashMap::new(),
+    };
+    // Banner at the top of the window where errors are shown
+    let error_bar = gtk::InfoBar::new();
+    error_bar.set_message_type(gtk::MessageType::Error);
+    error_bar.set_show_close_button(true);
+    error_bar.connect_response(|error_bar, _| error_bar.hide());
+    let error_label = gtk::Label::new(None);
+    error_label.set_line_wrap(true);
+    error_label.set_selectable(true);
+    error_label.set_xalign(0.0);
+    error_label.show();
+    if let Some(area) = error_bar
+        .get_content_area()
+        .and_then(|area| area.downcast::<gtk::Container>().ok())
+    {
+        area.add(&error_label);
     }
+    error_bar.set_no_show_all(true);
+    let root = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    root.pack_start(&error_bar, false, false, 0);
+    window.add(&root);
 
-    match layout {
-        Layout::Box(container) => window.add(&container.upcast::<gtk::Widget>()),
-        Layout::Grid(container) => window.add(&container.upcast::<gtk::Widget>()),
+    let mut next = 0;
+    let content = build_nodes(&config.nodes, &config.layout, &mut next, &mut widgets, &tx);
+    let wizard = match &config.pages {
+        Some(pages) => {
+            let (pages, wizard) = build_wizard(pages, &mut next, &mut widgets, &tx);
+            let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
+            container.pack_start(&content, false, false, 0);
+            container.pack_start(&pages, true, true, 0);
+            root.pack_start(&container, true, true, 0);
+            Some(wizard)
+        }
+        None => {
+            root.pack_start(&content, true, true, 0);
+            None
+        }
     };
+    let Widgets {
+        containers,
+        changes,
+        tables,
+        tabs,
+        inputs,
+        accelerators,
+        conditionals,
+    } = widgets;
+
+    for (i, (accelerator, button)) in accelerators.into_iter().enumerate() {
+        add_shortcut(
+            &window,
+            app,
+            &format!("button-{}", i),
+            &accelerator,
+            move || {
+                // Not clicked if the button is grayed out or not on screen, like when it is hidden
+                // or on a tab or page that is not shown
+                if button.is_sensitive() && button.get_mapped() {
+                    button.clicked();
+                }
+            },
+        );
+    }
+    for (i, (accelerator, _)) in config.shortcuts.iter().enumerate() {
+        let tx = tx.clone();
+        add_shortcut(
+            &window,
+            app,
+            &format!("shortcut-{}", i),
+            accelerator,
+            move || {
+                tx.send(MsgHandler::Shortcut(i)).unwrap();
+            },
+        );
+    }
+    if config.close_on_escape {
+        let window_clone = window.clone();
+        add_shortcut(&window, app, "close", "Escape", move || {
+            window_clone.close()
+        });
+    }
 
     let tx2 = tx.clone();
+    let window2 = window.clone();
+    let watches = config.watch.clone();
+    // Kept so the files are watched as long as the window is open
+    let mut monitors: Vec<gio::FileMonitor> = vec![];
+    let app = app.clone();
+    let show_error = move |text: &str| {
+        error_label.set_text(text);
+        error_bar.show();
+    };
     grx.attach(None, move |msg| {
         debug!("handler->gui: {:?}", msg);
         match msg {
-            MsgGui::Show { container, text } => {
+            MsgGui::Show {
+                container,
+                text,
+                scroll,
+                max_height,
+            } => {
                 if let Some(container) = containers.get(&container) {
                     container
                         .get_children()
                         .iter()
                         .for_each(|w| container.remove(w));
                     let label = gtk::Label::new(Some(&text));
-                    container.add(&label);
-                    container.show_all();
+                    let text = scrolled(label.upcast::<gtk::Widget>(), &scroll, max_height);
+                    container.add(&text);
+                    text.show_all();
                 } else {
                     warn!("could not find container with name {}", container);
                 }
@@ -188,7 +1068,9 @@ pub fn setup_gui(
                 container,
                 variable,
                 options,
+                selected,
             } => {
+                let changed = changes.get(&container).cloned();
                 if let Some(container) = containers.get(&container) {
                     container
                         .get_children()
@@ -196,11 +1078,40 @@ pub fn setup_gui(
                         .for_each(|w| container.remove(w));
                     let buttons = create_radio_buttons(
                         options.iter().map(|(a, b)| (a, b)).collect(),
+                        selected.as_ref(),
+                        variable,
+                        changed,
+                        tx2.clone(),
+                    );
+                    container.add(&buttons);
+                    buttons.show_all();
+                } else {
+                    warn!("could not find container with name {}", container);
+                }
+            }
+            MsgGui::MultiSelect {
+                container,
+                variable,
+                options,
+                selected,
+                separator,
+            } => {
+                let changed = changes.get(&container).cloned();
+                if let Some(container) = containers.get(&container) {
+                    container
+                        .get_children()
+                        .iter()
+                        .for_each(|w| container.remove(w));
+                    let buttons = create_check_buttons(
+                        options.iter().map(|(a, b)| (a, b)).collect(),
+                        &selected,
                         variable,
+                        separator,
+                        changed,
                         tx2.clone(),
                     );
                     container.add(&buttons);
-                    container.show_all();
+                    buttons.show_all();
                 } else {
                     warn!("could not find container with name {}", container);
                 }
@@ -208,25 +1119,33 @@ pub fn setup_gui(
             MsgGui::Image {
                 container,
                 filename,
+                reply,
             } => {
-                if let Some(container) = containers.get(&container) {
+                let answer = if let Some(container) = containers.get(&container) {
                     container
                         .get_children()
                         .iter()
                         .for_each(|w| container.remove(w));
-                    let pixbuf = gdk_pixbuf::Pixbuf::new_from_file_at_scale(
+                    match gdk_pixbuf::Pixbuf::new_from_file_at_scale(
                         &filename,
                         container.get_allocated_width(),
                         container.get_allocated_height(),
                         true,
-                    )
-                    .unwrap();
-                    let image = gtk::Image::new_from_pixbuf(Some(&pixbuf));
-                    container.add(&image);
-                    container.show_all();
+                    ) {
+                        Ok(pixbuf) => {
+                            let image = gtk::Image::new_from_pixbuf(Some(&pixbuf));
+                            container.add(&image);
+                            image.show_all();
+                            Reply::Done
+                        }
+                        Err(err) => {
+                            Reply::Failed(format!("could not load image {}: {}", filename, err))
+                        }
+                    }
                 } else {
-                    warn!("could not find container with name {}", container);
-                }
+                    Reply::Failed(format!("could not find container with name {}", container))
+                };
+                tx2.send(MsgHandler::Reply(reply, answer)).unwrap();
             }
             MsgGui::SetActive { node, active } => {
                 if let Some(node) = conditionals.get(&node) {
@@ -238,10 +1157,298 @@ pub fn setup_gui(
                     );
                 }
             }
+            MsgGui::SetVisible { node, visible } => {
+                if let Some(node) = conditionals.get(&node) {
+                    node.set_visible(visible);
+                } else {
+                    warn!(
+                        "could not find node with index {} in conditionals map",
+                        node
+                    );
+                }
+            }
+            MsgGui::SetText { node, text } => {
+                if let Some(input) = inputs.get(&node) {
+                    input.set_text(&text);
+                } else {
+                    warn!("could not find input with index {}", node);
+                }
+            }
+            MsgGui::Message { text, error, reply } => {
+                let dialog = gtk::MessageDialog::new(
+                    Some(&window2),
+                    gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+                    if error {
+                        gtk::MessageType::Error
+                    } else {
+                        gtk::MessageType::Info
+                    },
+                    gtk::ButtonsType::Ok,
+                    &text,
+                );
+                let tx = tx2.clone();
+                dialog.connect_response(move |dialog, _| {
+                    tx.send(MsgHandler::Reply(reply, Reply::Done)).unwrap();
+                    dialog.destroy();
+                });
+                dialog.show_all();
+            }
+            MsgGui::Confirm { text, reply } => {
+                let dialog = gtk::MessageDialog::new(
+                    Some(&window2),
+                    gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+                    gtk::MessageType::Question,
+                    gtk::ButtonsType::YesNo,
+                    &text,
+                );
+                let tx = tx2.clone();
+                dialog.connect_response(move |dialog, response| {
+                    let answer = match response {
+                        gtk::ResponseType::Yes => Reply::Done,
+                        _ => Reply::Cancelled,
+                    };
+                    tx.send(MsgHandler::Reply(reply, answer)).unwrap();
+                    dialog.destroy();
+                });
+                dialog.show_all();
+            }
+            MsgGui::Prompt {
+                text,
+                password,
+                reply,
+            } => {
+                let dialog = gtk::MessageDialog::new(
+                    Some(&window2),
+                    gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+                    gtk::MessageType::Question,
+                    gtk::ButtonsType::OkCancel,
+                    &text,
+                );
+                let entry = gtk::Entry::new();
+                entry.set_visibility(!password);
+                entry.set_activates_default(true);
+                dialog.set_default_response(gtk::ResponseType::Ok);
+                if let Some(area) = dialog
+                    .get_message_area()
+                    .and_then(|area| area.downcast::<gtk::Box>().ok())
+                {
+                    area.pack_start(&entry, false, false, 0);
+                }
+                let tx = tx2.clone();
+                dialog.connect_response(move |dialog, response| {
+                    let answer = match response {
+                        gtk::ResponseType::Ok => Reply::Text(entry.get_buffer().get_text()),
+                        _ => Reply::Cancelled,
+                    };
+                    tx.send(MsgHandler::Reply(reply, answer)).unwrap();
+                    dialog.destroy();
+                });
+                dialog.show_all();
+            }
+            MsgGui::SetTab { tabs: name, page } => {
+                if let Some(notebook) = tabs.get(&name) {
+                    notebook.set_current_page(Some(page));
+                } else {
+                    warn!("could not find tabs with name {}", name);
+                }
+            }
+            MsgGui::SetPage { page, valid } => {
+                if let Some(wizard) = &wizard {
+                    let last = page + 1 == wizard.pages;
+                    wizard.notebook.set_current_page(Some(page as u32));
+                    wizard.back.set_sensitive(page > 0);
+                    wizard.next.set_visible(!last);
+                    wizard.next.set_sensitive(valid);
+                    wizard.finish.set_visible(last);
+                    wizard.finish.set_sensitive(valid);
+                }
+            }
+            MsgGui::SetPageValid { valid } => {
+                if let Some(wizard) = &wizard {
+                    wizard.next.set_sensitive(valid);
+                    wizard.finish.set_sensitive(valid);
+                }
+            }
+            MsgGui::ShowError { text } => {
+                show_error(&text);
+            }
+            MsgGui::Notify {
+                title,
+                body,
+                icon,
+                urgency,
+            } => send_notification(
+                &app,
+                &title,
+                body.as_deref(),
+                icon.as_deref(),
+                urgency.as_ref(),
+            ),
+            MsgGui::CopyText { text, reply } => {
+                let answer = match window_clipboard(&window2) {
+                    Some(clipboard) => {
+                        clipboard.set_text(&text);
+                        clipboard.store();
+                        Reply::Done
+                    }
+                    None => Reply::Failed("could not get the clipboard".to_string()),
+                };
+                tx2.send(MsgHandler::Reply(reply, answer)).unwrap();
+            }
+            MsgGui::CopyImage { filename, reply } => {
+                let answer = match (
+                    window_clipboard(&window2),
+                    gdk_pixbuf::Pixbuf::new_from_file(&filename),
+                ) {
+                    (Some(clipboard), Ok(pixbuf)) => {
+                        clipboard.set_image(&pixbuf);
+                        clipboard.store();
+                        Reply::Done
+                    }
+                    (_, Err(err)) => {
+                        Reply::Failed(format!("could not load image {}: {}", filename, err))
+                    }
+                    (None, _) => Reply::Failed("could not get the clipboard".to_string()),
+                };
+                tx2.send(MsgHandler::Reply(reply, answer)).unwrap();
+            }
+            MsgGui::Paste { reply } => match window_clipboard(&window2) {
+                Some(clipboard) => {
+                    let tx = tx2.clone();
+                    clipboard.request_text(move |_, text| {
+                        let answer = match text {
+                            Some(text) => Reply::Text(text.to_string()),
+                            None => Reply::Failed("there is no text in the clipboard".to_string()),
+                        };
+                        tx.send(MsgHandler::Reply(reply, answer)).unwrap();
+                    })
+                }
+                None => tx2
+                    .send(MsgHandler::Reply(
+                        reply,
+                        Reply::Failed("could not get the clipboard".to_string()),
+                    ))
+                    .unwrap(),
+            },
+            MsgGui::Sleep { ms, reply } => {
+                let tx = tx2.clone();
+                glib::timeout_add_local(ms, move || {
+                    tx.send(MsgHandler::Reply(reply, Reply::Done)).unwrap();
+                    glib::Continue(false)
+                });
+            }
+            MsgGui::Watch { watch, path } => {
+                match watch_file(watch, &watches[watch], &path, &tx2) {
+                    Ok(monitor) => monitors.push(monitor),
+                    Err(err) => {
+                        error!("{}", err);
+                        show_error(&err);
+                    }
+                }
+            }
+            MsgGui::SetTitle { title } => window2.set_title(&title),
+            MsgGui::Close => window2.close(),
+            MsgGui::Table { table, rows } => {
+                if let Some(store) = tables.get(&table) {
+                    store.clear();
+                    for row in rows.iter() {
+                        let columns: Vec<u32> = (0..row.len() as u32).collect();
+                        let values: Vec<&dyn glib::ToValue> = row
+                            .iter()
+                            .map(|value| value as &dyn glib::ToValue)
+                            .collect();
+                        store.insert_with_values(None, &columns, &values);
+                    }
+                } else {
+                    warn!("could not find table with name {}", table);
+                }
+            }
         }
         glib::Continue(true)
     });
 
     window.show_all();
     tx.send(MsgHandler::Initialize).unwrap();
+
+    for (i, timer) in config.timers.iter().enumerate() {
+        let tx = tx.clone();
+        let once = timer.once;
+        glib::timeout_add_local(timer.interval, move || {
+            tx.send(MsgHandler::Timer(i)).unwrap();
+            glib::Continue(!once)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(yaml: &str) -> Validation {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn valid_variable_names() {
+        assert_eq!(valid_variable("$SAVE_TO$"), "$SAVE_TO_VALID$");
+        assert_eq!(valid_variable("NAME"), "NAME_VALID");
+        assert_eq!(valid_variable("$"), "$_VALID");
+    }
+
+    #[test]
+    fn first_broken_rule_is_reported() {
+        let rules = rules("regex: '^[a-z]+$'\nmin_length: 3\nmax_length: 5");
+        assert_eq!(validation_error(&rules, "abcd"), None);
+        assert_eq!(
+            validation_error(&rules, "AB"),
+            Some("has the wrong format".to_string())
+        );
+        assert_eq!(
+            validation_error(&rules, "ab"),
+            Some("must be at least 3 characters".to_string())
+        );
+        assert_eq!(
+            validation_error(&rules, "abcdef"),
+            Some("must be at most 5 characters".to_string())
+        );
+    }
+
+    #[test]
+    fn numbers_are_checked_against_min_and_max() {
+        let rules = rules("min: 1\nmax: 10");
+        assert_eq!(validation_error(&rules, " 5 "), None);
+        assert_eq!(
+            validation_error(&rules, "five"),
+            Some("must be a number".to_string())
+        );
+        assert_eq!(
+            validation_error(&rules, ""),
+            Some("must be a number".to_string())
+        );
+        assert_eq!(
+            validation_error(&rules, "0.5"),
+            Some("must be at least 1".to_string())
+        );
+        assert_eq!(
+            validation_error(&rules, "11"),
+            Some("must be at most 10".to_string())
+        );
+    }
+
+    #[test]
+    fn message_replaces_the_error() {
+        let rules = rules("min_length: 1\nmessage: Not a valid file name");
+        assert_eq!(validate(&rules, "a"), Ok(()));
+        assert_eq!(
+            validate(&rules, ""),
+            Err("Not a valid file name".to_string())
+        );
+    }
+
+    #[test]
+    fn path_must_exist() {
+        let rules = rules("path_exists: true");
+        assert_eq!(validation_error(&rules, "/"), None);
+        assert!(validation_error(&rules, "/no/such/path").is_some());
+    }
 }
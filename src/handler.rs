@@ -1,218 +1,1331 @@
 use super::config::*;
 use super::gui::*;
-use std::collections::{HashMap, HashSet};
+use regex::Regex;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::env;
 use std::io::prelude::*;
-use std::process::{Command, Stdio};
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::process::{ChildStdout, Command, Stdio};
+use std::thread;
 
 type Vars = HashMap<String, String>;
+type Lists = HashMap<String, Vec<String>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Conditional {
+    Node(usize),
+    Visible(usize),
+    Page(usize),
+}
+
+#[derive(Debug)]
+pub struct State {
+    vars: Vars,
+    // Variables set from a list of values, used to expand them into several arguments
+    lists: Lists,
+    // Conditions that depend on each variable
+    conditionals: HashMap<String, Vec<Conditional>>,
+    // Conditions that should be checked when any variable is set
+    templated: Vec<Conditional>,
+    // Conditions that should be checked again, since a variable they depend on has been set
+    changed: HashSet<Conditional>,
+    // Current page, if the config has pages
+    page: usize,
+    // Computed variables and their templates, sorted by Config
+    computed: Vec<(String, String)>,
+    // Sequences waiting for a reply from the gui, by the id of the request
+    pending: HashMap<usize, Sequence>,
+    next_reply: usize,
+    // Timers whose actions are still running, their next ticks are skipped until they are done
+    running_timers: HashSet<usize>,
+}
 
 #[derive(Debug)]
 pub enum MsgHandler {
     Initialize,
     Action(usize),
-    Var { variable: String, value: String },
+    // The value of a node has changed
+    Change(usize),
+    // Enter was pressed in an input
+    Activate(usize),
+    // A timer in the config is due
+    Timer(usize),
+    // A watched file has changed
+    Watch(usize),
+    // A shortcut in the config was pressed
+    Shortcut(usize),
+    Var {
+        variable: String,
+        value: String,
+    },
+    List {
+        variable: String,
+        values: Vec<String>,
+        separator: String,
+    },
+    NextPage,
+    PreviousPage,
+    Finish,
+    // Answer to a request a sequence of actions is waiting on
+    Reply(usize, Reply),
+}
+
+#[derive(Debug)]
+pub enum Reply {
+    // The dialog was closed, or the request was handled
+    Done,
+    // Answered no or cancelled in a dialog
+    Cancelled,
+    // Text entered in a prompt or read from the clipboard
+    Text(String),
+    Failed(String),
+}
+
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+// The last column gets the rest of the line, so it can contain whitespace
+fn split_whitespace_line(line: &str, columns: usize) -> Vec<String> {
+    let mut fields = vec![];
+    let mut rest = line.trim();
+    while fields.len() + 1 < columns && !rest.is_empty() {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        fields.push(rest[..end].to_string());
+        rest = rest[end..].trim_start();
+    }
+    if !rest.is_empty() {
+        fields.push(rest.to_string());
+    }
+    fields
+}
+
+fn json_to_string(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(string) => string.clone(),
+        serde_json::Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+fn parse_options(
+    text: &str,
+    delimiter: &Option<String>,
+    regex: &Option<Regex>,
+    json: &Option<OptionKeys>,
+) -> Result<Vec<(String, String)>, String> {
+    if let Some(keys) = json {
+        let items = match serde_json::from_str(text) {
+            Ok(serde_json::Value::Array(items)) => items,
+            _ => return Err("could not parse output as a JSON array".to_string()),
+        };
+        return Ok(items
+            .iter()
+            .map(|item| match item {
+                serde_json::Value::Object(fields) => {
+                    let value = fields.get(&keys.value).map(json_to_string);
+                    let label = keys.label.as_ref().and_then(|key| fields.get(key));
+                    let value = value.unwrap_or_default();
+                    let label = label.map(json_to_string).unwrap_or_else(|| value.clone());
+                    (value, label)
+                }
+                item => (json_to_string(item), json_to_string(item)),
+            })
+            .collect());
+    }
+    Ok(text
+        .lines()
+        .filter_map(|line| {
+            let (value, label) = if let Some(regex) = regex {
+                let captures = regex.captures(line)?;
+                let value = captures
+                    .name("value")
+                    .or_else(|| captures.get(1))
+                    .or_else(|| captures.get(0))?;
+                let label = captures.name("label").or_else(|| captures.get(2));
+                (value.as_str(), label.map(|label| label.as_str()))
+            } else if let Some(delimiter) = delimiter {
+                let mut parts = line.splitn(2, delimiter.as_str());
+                (parts.next().unwrap_or(""), parts.next())
+            } else {
+                (line, None)
+            };
+            Some((value.to_string(), label.unwrap_or(value).to_string()))
+        })
+        .collect())
+}
+
+fn parse_table(
+    text: &str,
+    format: &TableFormat,
+    header: bool,
+    columns: &[TableColumn],
+) -> Result<Vec<Vec<String>>, String> {
+    let rows: Vec<Vec<String>> = match format {
+        TableFormat::Json => match serde_json::from_str(text) {
+            Ok(serde_json::Value::Array(items)) => items
+                .iter()
+                .map(|item| match item {
+                    serde_json::Value::Object(fields) => columns
+                        .iter()
+                        .map(|column| {
+                            fields
+                                .get(column.key.as_ref().unwrap_or(&column.title))
+                                .map(json_to_string)
+                                .unwrap_or_default()
+                        })
+                        .collect(),
+                    serde_json::Value::Array(fields) => fields.iter().map(json_to_string).collect(),
+                    value => vec![json_to_string(value)],
+                })
+                .collect(),
+            _ => return Err("could not parse output as a JSON array".to_string()),
+        },
+        _ => text
+            .lines()
+            .skip(if header { 1 } else { 0 })
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| match format {
+                TableFormat::Csv => split_csv_line(line),
+                TableFormat::Tsv => line.split('\t').map(|field| field.to_string()).collect(),
+                _ => split_whitespace_line(line, columns.len()),
+            })
+            .collect(),
+    };
+    Ok(rows
+        .into_iter()
+        .map(|mut row| {
+            row.resize(columns.len(), String::new());
+            row
+        })
+        .collect())
+}
+
+fn expand(template: &str, vars: &Vars) -> String {
+    let mut string = template.to_string();
+    for (key, value) in vars.iter() {
+        string = string.replace(key, value);
+    }
+    string
 }
 
 fn check_condition(condition: &Condition, vars: &Vars) -> bool {
-    for (var, value) in condition.iter() {
-        let (var, negate) = if var.ends_with("!") {
-            (&var[..var.len() - 1], true)
+    match condition {
+        Condition::Equals {
+            variable,
+            value,
+            negate,
+        } => match vars.get(variable) {
+            Some(set_value) => (value == set_value) ^ negate,
+            None => *negate,
+        },
+        Condition::All(conditions) => conditions.iter().all(|c| check_condition(c, vars)),
+        Condition::Any(conditions) => conditions.iter().any(|c| check_condition(c, vars)),
+        Condition::Not(condition) => !check_condition(condition, vars),
+        Condition::Matches { variable, regex } => vars
+            .get(variable)
+            .is_some_and(|value| regex.is_match(value)),
+        Condition::LessThan { variable, value } => vars
+            .get(variable)
+            .and_then(|set_value| set_value.trim().parse::<f64>().ok())
+            .is_some_and(|set_value| set_value < *value),
+        Condition::GreaterThan { variable, value } => vars
+            .get(variable)
+            .and_then(|set_value| set_value.trim().parse::<f64>().ok())
+            .is_some_and(|set_value| set_value > *value),
+        Condition::IsSet(variable) => vars.contains_key(variable),
+        Condition::IsEmpty(variable) => vars.get(variable).is_none_or(|value| value.is_empty()),
+        Condition::FileExists(path) => Path::new(&expand(path, vars)).exists(),
+    }
+}
+
+// Maps each variable to the conditions that depend on it. Conditions using templates are
+// returned separately, since they depend on any variable
+fn map_conditionals(config: &Config) -> (HashMap<String, Vec<Conditional>>, Vec<Conditional>) {
+    let mut conditionals: HashMap<String, Vec<Conditional>> = HashMap::new();
+    let mut templated = vec![];
+    let nodes = config.all_nodes();
+    let pages = config.pages.iter().flatten();
+    let conditions = nodes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, node)| Some((Conditional::Node(i), node.active_when()?)))
+        .chain(
+            nodes
+                .iter()
+                .enumerate()
+                .filter_map(|(i, node)| Some((Conditional::Visible(i), node.visible_when()?))),
+        )
+        .chain(
+            pages
+                .enumerate()
+                .filter_map(|(i, page)| Some((Conditional::Page(i), page.valid_when.as_ref()?))),
+        );
+    for (conditional, condition) in conditions {
+        if condition.uses_templates() {
+            templated.push(conditional);
+        }
+        for var in condition.variables() {
+            conditionals
+                .entry(var.to_string())
+                .or_default()
+                .push(conditional);
+        }
+    }
+    (conditionals, templated)
+}
+
+fn page_valid(page: &WizardPage, vars: &Vars) -> bool {
+    match &page.valid_when {
+        Some(condition) => check_condition(condition, vars),
+        None => true,
+    }
+}
+
+impl State {
+    pub fn new(config: &Config) -> State {
+        let (conditionals, templated) = map_conditionals(config);
+        State {
+            vars: HashMap::new(),
+            lists: HashMap::new(),
+            conditionals,
+            templated,
+            changed: HashSet::new(),
+            page: 0,
+            computed: config.computed.clone(),
+            pending: HashMap::new(),
+            next_reply: 0,
+            running_timers: HashSet::new(),
+        }
+    }
+
+    // Id for a request to the gui, the gui sends it back with the reply
+    fn reply_id(&mut self) -> usize {
+        self.next_reply += 1;
+        self.next_reply
+    }
+
+    fn set_var(&mut self, variable: &str, value: String) {
+        self.set_value(variable, value);
+        self.update_computed(variable);
+    }
+
+    fn set_value(&mut self, variable: &str, value: String) {
+        if let Some(conditionals) = self.conditionals.get(variable) {
+            self.changed.extend(conditionals.iter());
+        }
+        self.changed.extend(self.templated.iter());
+        env::set_var(variable, &value);
+        self.vars.insert(variable.to_string(), value);
+        self.lists.remove(variable);
+    }
+
+    // Recomputes the computed variables that use the variable, and the ones that use those.
+    // They are sorted, so a computed variable is always updated after the ones it uses
+    fn update_computed(&mut self, variable: &str) {
+        let mut changed = vec![variable.to_string()];
+        for (name, template) in self.computed.clone().into_iter() {
+            if !changed.iter().any(|var| template.contains(var.as_str())) {
+                continue;
+            }
+            let value = expand(&template, &self.vars);
+            if self.vars.get(&name) != Some(&value) {
+                self.set_value(&name, value);
+                changed.push(name);
+            }
+        }
+    }
+
+    fn init_computed(&mut self) {
+        for (name, template) in self.computed.clone().into_iter() {
+            let value = expand(&template, &self.vars);
+            self.set_value(&name, value);
+        }
+    }
+
+    // Marks every condition to be checked, so that all nodes get their initial state
+    fn check_all(&mut self) {
+        self.changed
+            .extend(self.conditionals.values().flatten().copied());
+        self.changed.extend(self.templated.iter());
+    }
+
+    fn set_list(&mut self, variable: &str, values: Vec<String>, separator: &str) {
+        self.set_var(variable, values.join(separator));
+        self.lists.insert(variable.to_string(), values);
+    }
+
+    // An argument that is only a list variable is replaced by one argument per value
+    fn expand_arg(&self, arg: &str) -> Vec<String> {
+        match self.lists.get(arg) {
+            Some(values) => values.clone(),
+            None => vec![expand(arg, &self.vars)],
+        }
+    }
+}
+
+// Why a sequence of actions was stopped
+#[derive(Debug)]
+enum Stop {
+    // Answered no or cancelled in a dialog
+    Cancelled,
+    Failed(Failure),
+}
+
+// What to do once a sequence of actions is done, ok is false if it was stopped
+#[derive(Debug, Clone, Copy)]
+enum Then {
+    Nothing,
+    // Finish initializing, whether the initialize actions were stopped or not
+    Initialized,
+    // Show the page once its on_enter actions have run
+    ShowPage(usize),
+    // Go to a page if the on_leave actions of the current page were ok
+    EnterPage(usize),
+    // Run the finish actions if the on_leave actions of the last page were ok
+    Finish,
+    // Close the window if the finish actions were ok
+    Close,
+    // Let the timer run its actions again
+    TimerDone(usize),
+}
+
+// Actions left to run, kept in the state while waiting for a reply from the gui
+#[derive(Debug)]
+struct Sequence {
+    actions: VecDeque<Action>,
+    last_out: Option<ChildStdout>,
+    on_error: Option<Vec<Action>>,
+    // Running the on_error actions of another sequence
+    handling_error: bool,
+    // Set from the text the gui replies with
+    variable: Option<String>,
+    then: Then,
+}
+
+impl Sequence {
+    fn new(actions: &[Action], on_error: Option<&Vec<Action>>, then: Then) -> Sequence {
+        Sequence {
+            actions: actions.iter().cloned().collect(),
+            last_out: None,
+            on_error: on_error.cloned(),
+            handling_error: false,
+            variable: None,
+            then,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Failure {
+    message: String,
+    command: String,
+    status: Option<i32>,
+    stderr: String,
+}
+
+fn fail(message: String) -> Stop {
+    Stop::Failed(Failure {
+        message,
+        ..Default::default()
+    })
+}
+
+impl Failure {
+    // Message with the last lines of stderr, shown to the user
+    fn describe(&self) -> String {
+        let lines: Vec<&str> = self.stderr.lines().collect();
+        let tail = lines[lines.len().saturating_sub(STDERR_TAIL)..].join("\n");
+        if tail.is_empty() {
+            self.message.clone()
         } else {
-            (&var[..], false)
-        };
-        if match vars.get(var) {
-            Some(set_value) => (value != set_value) ^ negate,
-            None => !negate,
-        } {
-            return false;
-        };
+            format!("{}\n{}", self.message, tail)
+        }
     }
-    true
 }
 
-pub fn map_conditionals(config: &Config) -> HashMap<String, Vec<usize>> {
-    let mut conditionals: HashMap<String, Vec<usize>> = HashMap::new();
-    for (i, node) in config.nodes.iter().enumerate() {
-        let condition = match node {
-            Node::Button(btn) => btn.active_when.as_ref(),
-            Node::RadioButtons(_) => None,
-            Node::Container(_) => None,
-            Node::Input(inp) => inp.active_when.as_ref(),
-        };
-        if let Some(condition) = condition {
-            for (var, _) in condition.iter() {
-                let mut var = var.clone();
-                if var.ends_with("!") {
-                    var.pop();
+// Number of lines of stderr shown when a command fails
+const STDERR_TAIL: usize = 10;
+
+// The output of the last command, if there is one left to read
+fn read_stdout(last_out: &mut Option<ChildStdout>) -> Result<Option<String>, Stop> {
+    let mut stdout = match last_out.take() {
+        Some(stdout) => stdout,
+        None => return Ok(None),
+    };
+    let mut string = String::new();
+    stdout
+        .read_to_string(&mut string)
+        .map_err(|err| fail(format!("could not read stdout: {}", err)))?;
+    Ok(Some(string))
+}
+
+fn find_container(nodes: &[&Node], name: &str) -> Result<(), Stop> {
+    if nodes
+        .iter()
+        .any(|node| matches!(node, Node::Container(node) if node.name == name))
+    {
+        Ok(())
+    } else {
+        Err(fail(format!("could not find container with name {}", name)))
+    }
+}
+
+fn run_command(
+    command: &[String],
+    stdin: Option<ChildStdout>,
+    state: &State,
+) -> Result<Option<ChildStdout>, Stop> {
+    let program = command
+        .first()
+        .ok_or_else(|| fail("can't run an empty command".to_string()))?;
+    let args: Vec<String> = command
+        .iter()
+        .skip(1)
+        .flat_map(|arg| state.expand_arg(arg))
+        .collect();
+    let command_line = std::iter::once(program.clone())
+        .chain(args.iter().cloned())
+        .collect::<Vec<String>>()
+        .join(" ");
+    let child = Command::new(program)
+        .args(&args)
+        .stdin(match stdin {
+            Some(child_stdout) => Stdio::from(child_stdout),
+            None => Stdio::piped(),
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) => {
+            return Err(Stop::Failed(Failure {
+                message: format!("failed to start command {}: {}", command_line, err),
+                command: command_line,
+                ..Default::default()
+            }))
+        }
+    };
+    // Stderr is still passed on to our stderr, but also kept in case the command fails
+    let stderr = child.stderr.take().map(|stderr| {
+        thread::spawn(move || {
+            let mut kept = String::new();
+            for line in BufReader::new(stderr).split(b'\n').map_while(Result::ok) {
+                let mut out = io::stderr();
+                out.write_all(&line).and_then(|_| out.write_all(b"\n")).ok();
+                kept.push_str(&String::from_utf8_lossy(&line));
+                kept.push('\n');
+            }
+            kept
+        })
+    });
+    let status = child.wait();
+    let stderr = stderr
+        .and_then(|reader| reader.join().ok())
+        .unwrap_or_default();
+    match status {
+        Ok(status) if status.success() => Ok(child.stdout.take()),
+        Ok(status) => Err(Stop::Failed(Failure {
+            message: format!(
+                "command {} failed with status {}",
+                command_line,
+                status.code().unwrap_or(-1)
+            ),
+            command: command_line,
+            status: status.code(),
+            stderr,
+        })),
+        Err(err) => Err(Stop::Failed(Failure {
+            message: format!("failed to run command {}: {}", command_line, err),
+            command: command_line,
+            stderr,
+            ..Default::default()
+        })),
+    }
+}
+
+// Runs the actions in order, stops if one of them fails or is cancelled. Returns the id of
+// the request to the gui if it has to wait for a reply before running the rest
+fn run_actions(
+    sequence: &mut Sequence,
+    nodes: &[&Node],
+    state: &mut State,
+    gtx: &glib::Sender<MsgGui>,
+) -> Result<Option<usize>, Stop> {
+    let last_out = &mut sequence.last_out;
+    while let Some(action) = sequence.actions.pop_front() {
+        debug!("running action {:?}", action);
+        match &action {
+            Action::Run { command } => {
+                *last_out = run_command(command, last_out.take(), state)?;
+            }
+            Action::Show {
+                container,
+                scroll,
+                max_height,
+            } => {
+                find_container(nodes, container)?;
+                let text = read_stdout(last_out)?
+                    .ok_or_else(|| fail("can't show output, no stdout saved".to_string()))?;
+                gtx.send(MsgGui::Show {
+                    container: container.clone(),
+                    text,
+                    scroll: scroll.clone(),
+                    max_height: *max_height,
+                })
+                .unwrap();
+            }
+            Action::Var { name, value } => {
+                if let Some(value) = value {
+                    state.set_var(name, value.clone());
+                } else {
+                    let mut string = read_stdout(last_out)?
+                        .ok_or_else(|| fail(format!("can't set {}, no stdout saved", name)))?;
+                    if string.ends_with("\n") {
+                        string.pop();
+                    }
+                    state.set_var(name, string);
+                }
+            }
+            Action::Options {
+                variable,
+                container,
+                delimiter,
+                regex,
+                json,
+                default,
+                none,
+            } => {
+                find_container(nodes, container)?;
+                let string = read_stdout(last_out)?
+                    .ok_or_else(|| fail("can't create options, no stdout saved".to_string()))?;
+                let mut options = parse_options(&string, delimiter, regex, json)
+                    .map_err(|err| fail(format!("can't create options: {}", err)))?;
+                if let Some(none) = none {
+                    options.insert(0, ("".to_string(), none.clone()));
+                }
+                gtx.send(MsgGui::Options {
+                    container: container.clone(),
+                    variable: variable.to_owned(),
+                    options,
+                    selected: default.as_ref().map(|default| expand(default, &state.vars)),
+                })
+                .unwrap();
+            }
+            Action::MultiSelect {
+                variable,
+                container,
+                delimiter,
+                regex,
+                json,
+                default,
+                separator,
+            } => {
+                find_container(nodes, container)?;
+                let string = read_stdout(last_out)?
+                    .ok_or_else(|| fail("can't create options, no stdout saved".to_string()))?;
+                let options = parse_options(&string, delimiter, regex, json)
+                    .map_err(|err| fail(format!("can't create options: {}", err)))?;
+                gtx.send(MsgGui::MultiSelect {
+                    container: container.clone(),
+                    variable: variable.to_owned(),
+                    options,
+                    selected: default
+                        .iter()
+                        .map(|value| expand(value, &state.vars))
+                        .collect(),
+                    separator: separator.clone().unwrap_or_else(|| "\n".to_string()),
+                })
+                .unwrap();
+            }
+            Action::Image {
+                variable,
+                container,
+            } => {
+                find_container(nodes, container)?;
+                let filename = state.vars.get(variable).cloned().ok_or_else(|| {
+                    fail(format!("can't show image, variable {} not set", variable))
+                })?;
+                let reply = state.reply_id();
+                gtx.send(MsgGui::Image {
+                    container: container.clone(),
+                    filename,
+                    reply,
+                })
+                .unwrap();
+                return Ok(Some(reply));
+            }
+            Action::SetTab { tabs, page } => {
+                let page = expand(page, &state.vars);
+                let node = nodes
+                    .iter()
+                    .find_map(|node| match node {
+                        Node::Tabs(node) if &node.name == tabs => Some(node),
+                        _ => None,
+                    })
+                    .ok_or_else(|| fail(format!("could not find tabs with name {}", tabs)))?;
+                match node
+                    .pages
+                    .iter()
+                    .position(|tab| tab.title == page)
+                    .or_else(|| page.parse().ok())
+                {
+                    Some(page) if page < node.pages.len() => gtx
+                        .send(MsgGui::SetTab {
+                            tabs: tabs.clone(),
+                            page: page as u32,
+                        })
+                        .unwrap(),
+                    _ => {
+                        return Err(fail(format!(
+                            "could not find page {} in tabs {}",
+                            page, tabs
+                        )))
+                    }
+                }
+            }
+            Action::Table {
+                table,
+                format,
+                header,
+            } => {
+                let node = nodes
+                    .iter()
+                    .find_map(|node| match node {
+                        Node::Table(node) if &node.name == table => Some(node),
+                        _ => None,
+                    })
+                    .ok_or_else(|| fail(format!("could not find table with name {}", table)))?;
+                let string = read_stdout(last_out)?
+                    .ok_or_else(|| fail("can't fill table, no stdout saved".to_string()))?;
+                let rows = parse_table(
+                    &string,
+                    format.as_ref().unwrap_or(&TableFormat::Whitespace),
+                    header.unwrap_or(false),
+                    &node.columns,
+                )
+                .map_err(|err| fail(format!("can't fill table: {}", err)))?;
+                gtx.send(MsgGui::Table {
+                    table: table.clone(),
+                    rows,
+                })
+                .unwrap();
+            }
+            Action::Message { text } | Action::Error { text } => {
+                let text = match text {
+                    Some(text) => expand(text, &state.vars),
+                    None => read_stdout(last_out)?
+                        .ok_or_else(|| {
+                            fail("can't show message, no text or stdout saved".to_string())
+                        })?
+                        .trim_end()
+                        .to_string(),
                 };
-                let mut v = match conditionals.remove(&var) {
-                    Some(v) => v,
-                    None => vec![],
+                let reply = state.reply_id();
+                gtx.send(MsgGui::Message {
+                    text,
+                    error: matches!(action, Action::Error { .. }),
+                    reply,
+                })
+                .unwrap();
+                return Ok(Some(reply));
+            }
+            Action::Confirm { text } => {
+                let reply = state.reply_id();
+                gtx.send(MsgGui::Confirm {
+                    text: expand(text, &state.vars),
+                    reply,
+                })
+                .unwrap();
+                return Ok(Some(reply));
+            }
+            Action::Prompt {
+                text,
+                variable,
+                password,
+            } => {
+                let reply = state.reply_id();
+                gtx.send(MsgGui::Prompt {
+                    text: expand(text, &state.vars),
+                    password: *password,
+                    reply,
+                })
+                .unwrap();
+                sequence.variable = Some(variable.clone());
+                return Ok(Some(reply));
+            }
+            Action::Notify {
+                title,
+                body,
+                icon,
+                urgency,
+            } => gtx
+                .send(MsgGui::Notify {
+                    title: expand(title, &state.vars),
+                    body: body.as_ref().map(|body| expand(body, &state.vars)),
+                    icon: icon.as_ref().map(|icon| expand(icon, &state.vars)),
+                    urgency: urgency.clone(),
+                })
+                .unwrap(),
+            Action::CopyToClipboard { text, image } => {
+                let reply = state.reply_id();
+                let msg = match (text, image) {
+                    (_, Some(image)) => MsgGui::CopyImage {
+                        filename: expand(image, &state.vars),
+                        reply,
+                    },
+                    (Some(text), None) => MsgGui::CopyText {
+                        text: expand(text, &state.vars),
+                        reply,
+                    },
+                    (None, None) => {
+                        let mut text = read_stdout(last_out)?.ok_or_else(|| {
+                            fail("can't copy, no text or stdout saved".to_string())
+                        })?;
+                        if text.ends_with('\n') {
+                            text.pop();
+                        }
+                        MsgGui::CopyText { text, reply }
+                    }
                 };
-                v.push(i);
-                conditionals.insert(var, v);
+                gtx.send(msg).unwrap();
+                return Ok(Some(reply));
+            }
+            Action::Sleep { ms } => {
+                let reply = state.reply_id();
+                gtx.send(MsgGui::Sleep { ms: *ms, reply }).unwrap();
+                return Ok(Some(reply));
+            }
+            Action::SetTitle { title } => gtx
+                .send(MsgGui::SetTitle {
+                    title: expand(title, &state.vars),
+                })
+                .unwrap(),
+            Action::PasteFromClipboard { variable } => {
+                let reply = state.reply_id();
+                gtx.send(MsgGui::Paste { reply }).unwrap();
+                sequence.variable = Some(variable.clone());
+                return Ok(Some(reply));
             }
         }
     }
-    conditionals
+    Ok(None)
 }
 
-pub fn handle_msg(
+// Runs the actions of a sequence, if one of them fails the error variables are set and the
+// on_error actions of the sequence (or the config, if it has none) are run. Without on_error
+// actions the error is shown in the gui
+fn run_sequence(
+    mut sequence: Sequence,
     config: &Config,
-    vars: &mut Vars,
-    conditionals: &HashMap<String, Vec<usize>>,
-    msg: MsgHandler,
-    gtx: glib::Sender<MsgGui>,
+    nodes: &[&Node],
+    state: &mut State,
+    gtx: &glib::Sender<MsgGui>,
 ) {
-    debug!("gui->handler: {:?}", msg);
-    let mut conditionals_set = HashSet::new();
-    let actions = match msg {
-        MsgHandler::Action(i) => match &config.nodes[i] {
-            Node::Button(btn) => Some(&btn.on_click),
-            Node::RadioButtons(_) => None,
-            Node::Container(_) => None,
-            Node::Input(_) => None,
-        },
-        MsgHandler::Var { variable, value } => {
-            if let Some(nodes) = conditionals.get(&variable) {
-                nodes.iter().for_each(|i| {
-                    conditionals_set.insert(i.clone());
-                });
+    let result = run_actions(&mut sequence, nodes, state, gtx);
+    sequence_result(sequence, result, config, nodes, state, gtx);
+}
+
+// Runs the rest of a sequence once the gui has replied to the request it was waiting on
+fn resume_sequence(
+    reply_id: usize,
+    reply: Reply,
+    config: &Config,
+    nodes: &[&Node],
+    state: &mut State,
+    gtx: &glib::Sender<MsgGui>,
+) {
+    let mut sequence = match state.pending.remove(&reply_id) {
+        Some(sequence) => sequence,
+        None => {
+            warn!("no sequence waiting for reply {}", reply_id);
+            return;
+        }
+    };
+    let result = match reply {
+        Reply::Done => run_actions(&mut sequence, nodes, state, gtx),
+        Reply::Text(text) => {
+            if let Some(variable) = sequence.variable.take() {
+                state.set_var(&variable, text);
             }
-            vars.insert(variable.clone(), value.clone());
-            env::set_var(variable, value);
-            None
+            run_actions(&mut sequence, nodes, state, gtx)
         }
-        MsgHandler::Initialize => config.initialize.as_ref(),
+        Reply::Cancelled => {
+            debug!("cancelled, stopping");
+            Err(Stop::Cancelled)
+        }
+        Reply::Failed(message) => Err(fail(message)),
     };
-    if let Some(actions) = actions {
-        let mut last_out = None;
-        for action in actions.iter() {
-            debug!("running action {:?}", action);
-            match action {
-                Action::Run { command } => {
-                    let child = Command::new(&command[0])
-                        .args(command.iter().skip(1).map(|arg| {
-                            let mut arg = arg.clone();
-                            for (key, value) in vars.iter() {
-                                arg = arg.replace(key, value);
-                            }
-                            arg
-                        }))
-                        .stdin(match last_out.take() {
-                            Some(child_stdout) => Stdio::from(child_stdout),
-                            None => Stdio::piped(),
-                        })
-                        .stdout(Stdio::piped())
-                        .spawn();
-                    let mut child = match child {
-                        Ok(child) => child,
-                        Err(_) => {
-                            error!("failed to start command {:?}", command);
-                            break;
-                        }
-                    };
-                    match child.wait() {
-                        Ok(status) => {
-                            if !status.success() {
-                                error!(
-                                    "command {:?} failed to run with status {}",
-                                    command,
-                                    status.code().unwrap_or(-1)
-                                );
-                                break;
-                            }
-                        }
-                        Err(_) => {
-                            error!("failed to start command {:?}", command);
-                            break;
-                        }
-                    };
-                    last_out = child.stdout.take();
-                }
-                Action::Show { container } => {
-                    if let Some(mut stdout) = last_out.take() {
-                        let mut text = String::new();
-                        stdout.read_to_string(&mut text).unwrap();
-                        gtx.send(MsgGui::Show {
-                            container: container.clone(),
-                            text,
-                        })
-                        .unwrap();
-                    } else {
-                        warn!("can't show output, no stdout saved");
-                    }
+    sequence_result(sequence, result, config, nodes, state, gtx);
+}
+
+// Keeps the sequence if it is waiting for the gui, otherwise handles how it ended
+fn sequence_result(
+    sequence: Sequence,
+    result: Result<Option<usize>, Stop>,
+    config: &Config,
+    nodes: &[&Node],
+    state: &mut State,
+    gtx: &glib::Sender<MsgGui>,
+) {
+    let failure = match result {
+        Ok(Some(reply_id)) => {
+            state.pending.insert(reply_id, sequence);
+            return;
+        }
+        Ok(None) => {
+            let ok = !sequence.handling_error;
+            return after_sequence(sequence.then, ok, config, nodes, state, gtx);
+        }
+        Err(Stop::Cancelled) => {
+            return after_sequence(sequence.then, false, config, nodes, state, gtx);
+        }
+        Err(Stop::Failed(failure)) => failure,
+    };
+    if sequence.handling_error {
+        error!("on_error actions failed: {}", failure.message);
+        gtx.send(MsgGui::ShowError {
+            text: failure.describe(),
+        })
+        .unwrap();
+        return after_sequence(sequence.then, false, config, nodes, state, gtx);
+    }
+    error!("{}", failure.message);
+    state.set_var("$ERROR$", failure.message.clone());
+    state.set_var("$ERROR_COMMAND$", failure.command.clone());
+    state.set_var(
+        "$ERROR_STATUS$",
+        failure
+            .status
+            .map(|status| status.to_string())
+            .unwrap_or_default(),
+    );
+    state.set_var("$ERROR_STDERR$", failure.stderr.clone());
+    match sequence.on_error.as_ref().or(config.on_error.as_ref()) {
+        Some(on_error) => {
+            let on_error = Sequence {
+                handling_error: true,
+                ..Sequence::new(on_error, None, sequence.then)
+            };
+            run_sequence(on_error, config, nodes, state, gtx);
+        }
+        None => {
+            gtx.send(MsgGui::ShowError {
+                text: failure.describe(),
+            })
+            .unwrap();
+            after_sequence(sequence.then, false, config, nodes, state, gtx);
+        }
+    }
+}
+
+fn after_sequence(
+    then: Then,
+    ok: bool,
+    config: &Config,
+    nodes: &[&Node],
+    state: &mut State,
+    gtx: &glib::Sender<MsgGui>,
+) {
+    match then {
+        Then::Initialized => initialized(config, nodes, state, gtx),
+        Then::ShowPage(page) => show_page(config, state, page, gtx),
+        Then::EnterPage(page) if ok => enter_page(config, nodes, state, page, gtx),
+        Then::Finish if ok => match &config.finish {
+            Some(actions) => {
+                let sequence = Sequence::new(actions, None, Then::Close);
+                run_sequence(sequence, config, nodes, state, gtx);
+            }
+            None => gtx.send(MsgGui::Close).unwrap(),
+        },
+        Then::Close if ok => gtx.send(MsgGui::Close).unwrap(),
+        Then::TimerDone(i) => {
+            state.running_timers.remove(&i);
+        }
+        _ => {}
+    }
+}
+
+// Sets up what depends on the initialize actions, once they have run
+fn initialized(config: &Config, nodes: &[&Node], state: &mut State, gtx: &glib::Sender<MsgGui>) {
+    for (i, node) in nodes.iter().enumerate() {
+        if let Node::Input(InputNode {
+            default: Some(default),
+            ..
+        }) = node
+        {
+            gtx.send(MsgGui::SetText {
+                node: i,
+                text: expand(default, &state.vars),
+            })
+            .unwrap();
+        }
+    }
+    for (i, watch) in config.watch.iter().enumerate() {
+        gtx.send(MsgGui::Watch {
+            watch: i,
+            path: expand(&watch.path, &state.vars),
+        })
+        .unwrap();
+    }
+    state.check_all();
+    enter_page(config, nodes, state, 0, gtx);
+}
+
+fn enter_page(
+    config: &Config,
+    nodes: &[&Node],
+    state: &mut State,
+    page: usize,
+    gtx: &glib::Sender<MsgGui>,
+) {
+    let pages = match &config.pages {
+        Some(pages) if page < pages.len() => pages,
+        _ => return,
+    };
+    state.page = page;
+    match &pages[page].on_enter {
+        Some(actions) => {
+            let sequence = Sequence::new(actions, None, Then::ShowPage(page));
+            run_sequence(sequence, config, nodes, state, gtx);
+        }
+        None => show_page(config, state, page, gtx),
+    }
+}
+
+fn show_page(config: &Config, state: &State, page: usize, gtx: &glib::Sender<MsgGui>) {
+    if let Some(page_config) = config.pages.iter().flatten().nth(page) {
+        gtx.send(MsgGui::SetPage {
+            page,
+            valid: page_valid(page_config, &state.vars),
+        })
+        .unwrap();
+    }
+}
+
+// Runs the on_leave actions of the current page, then does what comes next if they were ok
+fn leave_page(
+    config: &Config,
+    nodes: &[&Node],
+    state: &mut State,
+    then: Then,
+    gtx: &glib::Sender<MsgGui>,
+) {
+    let page = state.page;
+    match config
+        .pages
+        .as_ref()
+        .and_then(|pages| pages[page].on_leave.as_ref())
+    {
+        Some(actions) => run_sequence(
+            Sequence::new(actions, None, then),
+            config,
+            nodes,
+            state,
+            gtx,
+        ),
+        None => after_sequence(then, true, config, nodes, state, gtx),
+    }
+}
+
+pub fn handle_msg(config: &Config, state: &mut State, msg: MsgHandler, gtx: glib::Sender<MsgGui>) {
+    debug!("gui->handler: {:?}", msg);
+    let nodes = config.all_nodes();
+    // Runs actions that don't lead to anything else
+    let run = |actions: &[Action], on_error: Option<&Vec<Action>>, state: &mut State| {
+        let sequence = Sequence::new(actions, on_error, Then::Nothing);
+        run_sequence(sequence, config, &nodes, state, &gtx);
+    };
+    match msg {
+        MsgHandler::Initialize => {
+            state.init_computed();
+            state.check_all();
+            update_conditionals(config, &nodes, state, &gtx);
+            match &config.initialize {
+                Some(actions) => {
+                    let sequence = Sequence::new(actions, None, Then::Initialized);
+                    run_sequence(sequence, config, &nodes, state, &gtx);
                 }
-                Action::Var { name, value } => {
-                    if let Some(nodes) = conditionals.get(name) {
-                        nodes.iter().for_each(|i| {
-                            conditionals_set.insert(i.clone());
-                        });
-                    }
-                    if let Some(value) = value {
-                        vars.insert(name.clone(), value.clone());
-                        env::set_var(name, value);
-                    } else if let Some(mut stdout) = last_out.take() {
-                        let mut string = String::new();
-                        stdout.read_to_string(&mut string).unwrap();
-                        if string.ends_with("\n") {
-                            string.pop();
-                        }
-                        vars.insert(name.clone(), string.clone());
-                        env::set_var(name, string);
-                    } else {
-                        warn!("can't show output, no stdout saved");
-                    }
+                None => initialized(config, &nodes, state, &gtx),
+            }
+        }
+        MsgHandler::Action(i) => {
+            if let Node::Button(btn) = nodes[i] {
+                run(&btn.on_click, btn.on_error.as_ref(), state);
+            }
+        }
+        MsgHandler::Change(i) => {
+            if let Some(actions) = nodes[i].on_change() {
+                run(actions, nodes[i].on_error(), state);
+            }
+        }
+        MsgHandler::Activate(i) => {
+            if let Node::Input(InputNode {
+                on_activate: Some(actions),
+                ..
+            }) = nodes[i]
+            {
+                run(actions, nodes[i].on_error(), state);
+            }
+        }
+        MsgHandler::Timer(i) => {
+            let timer = &config.timers[i];
+            let active = match &timer.active_when {
+                Some(condition) => check_condition(condition, &state.vars),
+                None => true,
+            };
+            if !active {
+                debug!("timer {} is not active, skipping", i);
+            } else if !state.running_timers.insert(i) {
+                debug!("timer {} is still running, skipping", i);
+            } else {
+                let on_error = timer.on_error.as_ref();
+                let sequence = Sequence::new(&timer.actions, on_error, Then::TimerDone(i));
+                run_sequence(sequence, config, &nodes, state, &gtx);
+            }
+        }
+        MsgHandler::Watch(i) => {
+            let watch = &config.watch[i];
+            run(&watch.actions, watch.on_error.as_ref(), state);
+        }
+        MsgHandler::Shortcut(i) => {
+            let (_, actions) = &config.shortcuts[i];
+            run(actions, None, state);
+        }
+        MsgHandler::Var { variable, value } => state.set_var(&variable, value),
+        MsgHandler::List {
+            variable,
+            values,
+            separator,
+        } => state.set_list(&variable, values, &separator),
+        MsgHandler::NextPage => {
+            let page = state.page + 1;
+            leave_page(config, &nodes, state, Then::EnterPage(page), &gtx);
+        }
+        MsgHandler::PreviousPage => {
+            if state.page > 0 {
+                let page = state.page - 1;
+                leave_page(config, &nodes, state, Then::EnterPage(page), &gtx);
+            }
+        }
+        MsgHandler::Finish => leave_page(config, &nodes, state, Then::Finish, &gtx),
+        MsgHandler::Reply(reply_id, reply) => {
+            resume_sequence(reply_id, reply, config, &nodes, state, &gtx)
+        }
+    };
+    update_conditionals(config, &nodes, state, &gtx);
+}
+
+// Checks the conditions that depend on variables that have been set, and updates the gui
+fn update_conditionals(
+    config: &Config,
+    nodes: &[&Node],
+    state: &mut State,
+    gtx: &glib::Sender<MsgGui>,
+) {
+    let changed: Vec<Conditional> = state.changed.drain().collect();
+    for conditional in changed.into_iter() {
+        match conditional {
+            Conditional::Node(i) => {
+                if let Some(condition) = nodes[i].active_when() {
+                    gtx.send(MsgGui::SetActive {
+                        node: i,
+                        active: check_condition(condition, &state.vars),
+                    })
+                    .unwrap();
                 }
-                Action::Options {
-                    variable,
-                    container,
-                } => {
-                    if let Some(mut stdout) = last_out.take() {
-                        let mut string = String::new();
-                        stdout.read_to_string(&mut string).unwrap();
-                        let lines = string.lines();
-                        gtx.send(MsgGui::Options {
-                            container: container.clone(),
-                            variable: variable.to_owned(),
-                            options: lines.map(|a| (a.to_string(), a.to_string())).collect(),
-                        })
-                        .unwrap();
-                    } else {
-                        warn!("can't create options, no stdout saved");
-                    }
+            }
+            Conditional::Visible(i) => {
+                if let Some(condition) = nodes[i].visible_when() {
+                    gtx.send(MsgGui::SetVisible {
+                        node: i,
+                        visible: check_condition(condition, &state.vars),
+                    })
+                    .unwrap();
                 }
-                Action::Image {
-                    variable,
-                    container,
-                } => {
-                    if let Some(value) = vars.get(variable) {
-                        gtx.send(MsgGui::Image {
-                            container: container.clone(),
-                            filename: value.clone(),
+            }
+            Conditional::Page(i) => {
+                if let Some(page) = config.pages.iter().flatten().nth(i) {
+                    if i == state.page {
+                        gtx.send(MsgGui::SetPageValid {
+                            valid: page_valid(page, &state.vars),
                         })
                         .unwrap();
-                    } else {
-                        warn!("variable {} not set", variable);
                     }
                 }
             }
         }
     }
-    for i in conditionals_set.into_iter() {
-        let condition = match &config.nodes[i] {
-            Node::Button(btn) => btn.active_when.as_ref(),
-            Node::RadioButtons(_) => None,
-            Node::Container(_) => None,
-            Node::Input(inp) => inp.active_when.as_ref(),
-        };
-        if let Some(condition) = condition {
-            gtx.send(MsgGui::SetActive {
-                node: i,
-                active: check_condition(condition, vars),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(titles: &[&str]) -> Vec<TableColumn> {
+        titles
+            .iter()
+            .map(|title| TableColumn {
+                title: title.to_string(),
+                variable: None,
+                key: None,
             })
-            .unwrap();
-        }
+            .collect()
+    }
+
+    #[test]
+    fn csv_quoted_commas_and_escaped_quotes() {
+        assert_eq!(
+            split_csv_line(r#"a,"b, c","say ""hi""",,d"#),
+            vec!["a", "b, c", r#"say "hi""#, "", "d"]
+        );
+    }
+
+    #[test]
+    fn whitespace_last_column_gets_the_rest() {
+        assert_eq!(
+            split_whitespace_line("  1   two  three four ", 3),
+            vec!["1", "two", "three four"]
+        );
+        assert_eq!(split_whitespace_line("1", 3), vec!["1"]);
+    }
+
+    #[test]
+    fn table_skips_header_and_blank_lines() {
+        let text = "NAME SIZE\nfoo 1\n\n  \nbar 2\n";
+        let rows = parse_table(text, &TableFormat::Whitespace, true, &columns(&["a", "b"]));
+        assert_eq!(rows.unwrap(), vec![vec!["foo", "1"], vec!["bar", "2"]]);
+    }
+
+    #[test]
+    fn table_pads_short_rows_and_cuts_long_rows() {
+        let rows = parse_table(
+            "a\na,b,c\n",
+            &TableFormat::Csv,
+            false,
+            &columns(&["a", "b"]),
+        );
+        assert_eq!(rows.unwrap(), vec![vec!["a", ""], vec!["a", "b"]]);
+        let rows = parse_table("a\tb\tc\n", &TableFormat::Tsv, false, &columns(&["a", "b"]));
+        assert_eq!(rows.unwrap(), vec![vec!["a", "b"]]);
+    }
+
+    #[test]
+    fn table_json_objects_and_arrays() {
+        let mut columns = columns(&["Name", "size"]);
+        columns[0].key = Some("name".to_string());
+        let text = r#"[{"name": "foo", "size": 1}, {"name": "bar"}, ["baz", 2], "qux"]"#;
+        let rows = parse_table(text, &TableFormat::Json, false, &columns);
+        assert_eq!(
+            rows.unwrap(),
+            vec![
+                vec!["foo", "1"],
+                vec!["bar", ""],
+                vec!["baz", "2"],
+                vec!["qux", ""],
+            ]
+        );
+    }
+
+    #[test]
+    fn table_bad_json_is_an_error() {
+        let columns = columns(&["a"]);
+        assert!(parse_table("not json", &TableFormat::Json, false, &columns).is_err());
+        assert!(parse_table(r#"{"a": 1}"#, &TableFormat::Json, false, &columns).is_err());
+    }
+
+    fn options(text: &str, delimiter: Option<&str>, regex: Option<&str>) -> Vec<(String, String)> {
+        let delimiter = delimiter.map(|delimiter| delimiter.to_string());
+        let regex = regex.map(|regex| Regex::new(regex).unwrap());
+        parse_options(text, &delimiter, &regex, &None).unwrap()
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(value, label)| (value.to_string(), label.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn options_without_delimiter_use_the_line_as_label() {
+        assert_eq!(
+            options("a b\nc\n", None, None),
+            pairs(&[("a b", "a b"), ("c", "c")])
+        );
+    }
+
+    #[test]
+    fn options_split_on_the_first_delimiter() {
+        assert_eq!(
+            options("a 1 x\nb\n", Some(" "), None),
+            pairs(&[("a", "1 x"), ("b", "b")])
+        );
+    }
+
+    #[test]
+    fn options_named_regex_groups() {
+        let regex = r"(?P<label>\w+)=(?P<value>\d+)";
+        assert_eq!(
+            options("one=1\ntwo=2\n", None, Some(regex)),
+            pairs(&[("1", "one"), ("2", "two")])
+        );
+    }
+
+    #[test]
+    fn options_positional_regex_groups_skip_lines_that_dont_match() {
+        assert_eq!(
+            options("1:one\n# comment\n2:two\n", None, Some(r"(\d+):(\w+)")),
+            pairs(&[("1", "one"), ("2", "two")])
+        );
+        assert_eq!(options("1\nx\n", None, Some(r"\d")), pairs(&[("1", "1")]));
+    }
+
+    #[test]
+    fn options_json_with_and_without_label() {
+        let text = r#"[{"id": 1, "name": "one"}, {"id": "2"}, "three"]"#;
+        let keys = OptionKeys {
+            value: "id".to_string(),
+            label: Some("name".to_string()),
+        };
+        assert_eq!(
+            parse_options(text, &None, &None, &Some(keys)).unwrap(),
+            pairs(&[("1", "one"), ("2", "2"), ("three", "three")])
+        );
+        let keys = OptionKeys {
+            value: "id".to_string(),
+            label: None,
+        };
+        assert_eq!(
+            parse_options(text, &None, &None, &Some(keys)).unwrap(),
+            pairs(&[("1", "1"), ("2", "2"), ("three", "three")])
+        );
+    }
+
+    #[test]
+    fn options_bad_json_is_an_error() {
+        let keys = OptionKeys {
+            value: "id".to_string(),
+            label: None,
+        };
+        assert!(parse_options("{}", &None, &None, &Some(keys)).is_err());
     }
 }
@@ -1,3 +1,4 @@
+extern crate gdk;
 extern crate gdk_pixbuf;
 extern crate gio;
 extern crate glib;
@@ -13,7 +14,6 @@ pub mod handler;
 
 use gio::prelude::*;
 use gtk::Application;
-use std::collections::HashMap;
 use std::env;
 use std::sync::mpsc;
 use std::thread;
@@ -37,11 +37,9 @@ fn main() {
 
         let config_clone = config.clone();
         thread::spawn(move || {
-            let mut vars = HashMap::new();
-            let conditional_map = map_conditionals(&config_clone);
-            rx.iter().for_each(|msg| {
-                handle_msg(&config_clone, &mut vars, &conditional_map, msg, gtx.clone())
-            });
+            let mut state = State::new(&config_clone);
+            rx.iter()
+                .for_each(|msg| handle_msg(&config_clone, &mut state, msg, gtx.clone()));
         });
 
         setup_gui(tx.clone(), grx, &config, app);